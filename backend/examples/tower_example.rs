@@ -36,7 +36,18 @@ fn main() {
         random_seed: None,
         scenario: Some(Scenario {
             path: "tower_example".to_string(),
+            lua_source: None,
         }),
+        scenario_search_path: vec![format!("{}/lua", env!("CARGO_MANIFEST_DIR"))],
+        observation: None,
+        feature_layers: vec![],
+        agent_factions: vec![],
+        max_episode_time: None,
+        max_steps: None,
+        tick_length: None,
+        action_repeat: None,
+        replay_dir: None,
+        replay_file: None,
     };
 
     let mut cfg_msg: Vec<u8> = vec![];
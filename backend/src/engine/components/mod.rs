@@ -28,6 +28,7 @@ pub(super) fn register_world_components(world: &mut World) {
     world.register::<Pos>();
     world.register::<Heading>();
     world.register::<Move>();
+    world.register::<NavPath>();
     world.register::<Movable>();
     world.register::<Static>();
     world.register::<MovedFlag>();
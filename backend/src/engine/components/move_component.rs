@@ -20,9 +20,22 @@ pub struct Movable;
 #[component(NullStorage)]
 pub struct Static;
 
-#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum MoveBehavior {
     Straight,
+    /// Routes around `Static` units using the `NavGrid`
+    Pathfind,
+}
+
+/// The cached route of a unit moving with `MoveBehavior::Pathfind`.
+#[derive(Component, Clone, PartialEq, Default)]
+#[component(HashMapStorage)]
+pub struct NavPath {
+    /// Stored in reverse, so the next waypoint is last
+    pub waypoints: Vec<Pos>,
+    pub goal_cell: (usize, usize),
+    /// The `NavGrid` generation this was computed against
+    pub generation: u64,
 }
 
 #[derive(Copy, Clone, PartialEq)]
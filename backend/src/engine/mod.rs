@@ -1,34 +1,63 @@
 pub mod components;
 pub mod systems;
 pub mod resources;
+pub mod replay;
+pub mod snapshot;
+pub mod vec_rts;
+#[cfg(test)]
+mod test_util;
 
 use self::resources::*;
 
-use scaii_defs::protos::{Action, MultiMessage};
+use scaii_defs::protos::{Action, MultiMessage, ScaiiPacket};
 
 use specs::{Dispatcher, World};
 
 use self::components::FactionId;
+use self::replay::{Replay, ReplayPlayer, ReplayRecorder, ReplayScenario};
 use self::systems::lua::LuaSystem;
 
+use error::SkyError;
+use protos::{Config, ObservationConfig};
+
+use std::error::Error;
 use std::path::PathBuf;
 
 pub struct Rts<'a, 'b> {
     world: World,
     pub lua_path: Option<PathBuf>,
+    /// Inline scenario source, used instead of `lua_path` when present.
+    pub lua_source: Option<String>,
     pub initialized: bool,
     pub render: bool,
+    /// Overrides for the scenario's observation grid from the `Config`.
+    pub observation: Option<ObservationConfig>,
+    /// Overrides the scenario's feature layers if non-empty.
+    pub feature_layers: Vec<String>,
+    /// Overrides the factions the agent controls if non-empty.
+    pub agent_factions: Vec<usize>,
+    /// Overrides the scenario's time limit, in simulated seconds.
+    pub max_episode_time: Option<f64>,
+    /// Overrides the scenario's limit on simulated frames.
+    pub max_steps: Option<u64>,
+    /// The length of a simulated frame in seconds, 1/60 by default.
+    pub tick_length: Option<f64>,
+    /// How many frames to simulate for each action, 1 by default.
+    pub action_repeat: Option<usize>,
 
     sim_systems: Dispatcher<'a, 'b>,
     lua_sys: LuaSystem,
     out_systems: Dispatcher<'a, 'b>,
+
+    recorder: Option<ReplayRecorder>,
+    player: Option<ReplayPlayer>,
 }
 
 impl<'a, 'b> Rts<'a, 'b> {
     pub fn new() -> Self {
         use specs::DispatcherBuilder;
         use self::systems::{AttackSystem, CleanupSystem, CollisionSystem, InputSystem, MoveSystem,
-                            RenderSystem, StateBuildSystem};
+                            RenderSystem, StateBuildSystem, StateUpdateSystem, VisibilitySystem};
 
         let mut world = World::new();
         components::register_world_components(&mut world);
@@ -37,14 +66,16 @@ impl<'a, 'b> Rts<'a, 'b> {
         let simulation_builder: Dispatcher = DispatcherBuilder::new()
             .add(InputSystem::new(), "input", &[])
             .add(MoveSystem::new(), "movement", &["input"])
-            .add(CollisionSystem, "collision", &["movement"])
+            .add(CollisionSystem::new(), "collision", &["movement"])
             .add(AttackSystem, "attack", &["collision"])
             .build();
 
         let output_builder = DispatcherBuilder::new()
-            .add(RenderSystem {}, "render", &[])
-            .add(CleanupSystem, "cleanup", &["render"])
-            .add(StateBuildSystem::new(), "state", &["cleanup"])
+            .add(RenderSystem::new(), "render", &[])
+            .add(VisibilitySystem::new(), "visibility", &["render"])
+            .add(StateUpdateSystem::new(), "state_update", &["visibility"])
+            .add(StateBuildSystem::new(), "state", &["state_update"])
+            .add(CleanupSystem, "cleanup", &["state"])
             .build();
 
         let lua_sys = LuaSystem::new();
@@ -53,13 +84,94 @@ impl<'a, 'b> Rts<'a, 'b> {
             world,
             lua_sys,
             lua_path: None,
+            lua_source: None,
             initialized: false,
             render: false,
+            observation: None,
+            feature_layers: Vec::new(),
+            agent_factions: Vec::new(),
+            max_episode_time: None,
+            max_steps: None,
+            tick_length: None,
+            action_repeat: None,
             sim_systems: simulation_builder,
             out_systems: output_builder,
+            recorder: None,
+            player: None,
+        }
+    }
+
+    /// Applies the RTS-specific `Config`, which takes effect when the
+    /// scenario is first loaded.
+    pub fn configure(&mut self, cfg: &Config) -> Result<(), SkyError> {
+        use protos::{Scenario, Seed};
+        use util;
+
+        self.render = cfg.emit_viz.unwrap_or_default();
+        self.observation = cfg.observation.clone();
+        self.feature_layers = cfg.feature_layers.clone();
+        self.agent_factions = cfg.agent_factions.iter().map(|f| *f as usize).collect();
+        self.max_episode_time = cfg.max_episode_time;
+        self.max_steps = cfg.max_steps;
+        self.tick_length = cfg.tick_length;
+        self.action_repeat = cfg.action_repeat.map(|ticks| ticks as usize);
+        self.recorder = cfg.replay_dir
+            .as_ref()
+            .map(|dir| ReplayRecorder::new(PathBuf::from(dir), cfg));
+        self.player = None;
+
+        if let Some(Seed { ref seed }) = cfg.random_seed {
+            self.seed(seed);
+        }
+
+        match cfg.scenario {
+            Some(Scenario {
+                lua_source: Some(ref src),
+                ..
+            }) => {
+                self.lua_source = Some(src.clone());
+                self.lua_path = None;
+
+                Ok(())
+            }
+            Some(Scenario { ref path, .. }) => {
+                self.lua_path = Some(util::resolve_scenario(path, &cfg.scenario_search_path)?);
+                self.lua_source = None;
+
+                Ok(())
+            }
+            _ => Err(SkyError::Config("No scenario given".to_string())),
         }
     }
 
+    /// Plays back the replay at `cfg.replay_file` instead of taking actions
+    /// from the agent, using the `Config` it was recorded with. Only
+    /// `emit_viz` is taken from `cfg`.
+    pub fn play_replay(&mut self, cfg: &Config) -> Result<(), SkyError> {
+        use prost::Message;
+        use std::path::Path;
+
+        let path = match cfg.replay_file {
+            Some(ref path) => path,
+            None => {
+                return Err(SkyError::Config(
+                    "Replay mode needs a replay_file to play".to_string(),
+                ))
+            }
+        };
+
+        let player = ReplayPlayer::new(Replay::load(Path::new(path))?)?;
+
+        let mut replay_cfg = Config::decode(&player.replay.config[..])?;
+        replay_cfg.replay_dir = None;
+        replay_cfg.emit_viz = cfg.emit_viz;
+
+        self.configure(&replay_cfg)?;
+        self.player = Some(player);
+
+        Ok(())
+    }
+
     /// Causes the random number state to diverge
     /// so that if, say, the RTS had previously been
     /// serialized at this state before calling this function,
@@ -72,55 +184,192 @@ impl<'a, 'b> Rts<'a, 'b> {
         util::diverge(rng);
     }
 
-    fn init(&mut self) {
-        self.lua_sys
-            .init(
-                &mut self.world,
-                self.lua_path.as_ref().expect("No Lua file loaded"),
-            )
-            .expect("Could not load Lua file");
+    /// Seeds the RNG, every subsequent episode is reseeded
+    /// deterministically from this seed.
+    pub fn seed(&mut self, seed: &[u64]) {
+        use rand::Isaac64Rng;
+        use util;
+
+        let mut seed_rng = util::make_seeded_rng(seed);
 
-        self.lua_sys
-            .load_scenario(&mut self.world)
-            .expect("Could not initialize scenario");
+        util::reseed_from(&mut *self.world.write_resource::<Isaac64Rng>(), &mut seed_rng);
+        self.world.write_resource::<SeedRng>().0 = seed_rng;
     }
 
-    pub fn reset(&mut self) -> MultiMessage {
-        use rand::Isaac64Rng;
-        use util;
-        use scaii_defs::protos::ScaiiPacket;
-        use scaii_defs::protos;
-        use shred::RunNow;
-        use self::resources::COLLISION_MARGIN;
+    fn init(&mut self) -> Result<(), SkyError> {
+        match (&self.lua_source, &self.lua_path) {
+            (&Some(ref src), _) => {
+                self.lua_sys
+                    .init_from_source(&mut self.world, src, "Inline Lua Scenario Script")?
+            }
+            (&None, &Some(ref path)) => self.lua_sys.init(&mut self.world, path)?,
+            (&None, &None) => {
+                return Err(SkyError::Config(
+                    "No scenario loaded, was the backend configured?".to_string(),
+                ))
+            }
+        }
 
-        if !self.initialized {
-            self.init();
-            self.initialized = true;
+        // Problems with replays shouldn't stop the episode from running,
+        // and an unreadable script has already failed above
+        if let Some(ref mut recorder) = self.recorder {
+            match ReplayScenario::new(&self.lua_source, &self.lua_path) {
+                Ok(scenario) => recorder.scenario = scenario,
+                Err(e) => self.world.write_resource::<Errors>().0.push(e),
+            }
         }
 
-        *self.world.write_resource::<SkyCollisionWorld>() =
-            SkyCollisionWorld::new(COLLISION_MARGIN);
+        if let Some(ref player) = self.player {
+            let res = ReplayScenario::new(&self.lua_source, &self.lua_path).and_then(|scenario| {
+                if scenario.hash == player.replay.scenario.hash {
+                    Ok(())
+                } else {
+                    Err(SkyError::Replay(
+                        "The scenario has changed since the replay was recorded".to_string(),
+                    ))
+                }
+            });
+
+            if let Err(e) = res {
+                self.world.write_resource::<Errors>().0.push(e);
+            }
+        }
 
-        self.world.delete_all();
-        // Do a fast reseed so it doesn't start looping the RNG state
-        // after too many episodes
-        {
-            let rng = &mut *self.world.write_resource::<Isaac64Rng>();
-            util::diverge(rng);
+        self.lua_sys.load_scenario(&mut self.world)?;
+        self.apply_agent_factions()?;
+        self.apply_episode_limits()?;
+        self.apply_timing()?;
+        self.apply_observation()
+    }
 
-            self.world.write_resource::<Episode>().0 += 1;
-            self.world.write_resource::<Terminal>().0 = false;
+    /// Applies the configured tick length and action repeat.
+    fn apply_timing(&mut self) -> Result<(), SkyError> {
+        if let Some(tick_length) = self.tick_length {
+            if tick_length.is_nan() || tick_length <= 0.0 {
+                return Err(SkyError::Config(format!(
+                    "Invalid tick_length {}, it must be positive",
+                    tick_length
+                )));
+            }
+            self.world.write_resource::<DeltaT>().0 = tick_length;
         }
 
-        self.lua_sys
-            .reset(&mut self.world)
-            .expect("Could not reset world from Lua");
+        if let Some(ticks) = self.action_repeat {
+            if ticks == 0 {
+                return Err(SkyError::Config(
+                    "Invalid action_repeat 0, at least one tick is needed per action".to_string(),
+                ));
+            }
+            self.world.write_resource::<ActionRepeat>().ticks = ticks;
+        }
 
-        // Ensure changes and render
-        self.world.maintain();
-        self.sim_systems.dispatch_seq(&self.world.res);
-        self.lua_sys.run_now(&self.world.res);
-        self.out_systems.dispatch_seq(&self.world.res);
+        Ok(())
+    }
+
+    /// Applies any configured time limits on top of the scenario's.
+    fn apply_episode_limits(&mut self) -> Result<(), SkyError> {
+        let limits = &mut *self.world.write_resource::<EpisodeLimits>();
+
+        if let Some(max_time) = self.max_episode_time {
+            limits.max_time = Some(max_time);
+        }
+        if let Some(max_steps) = self.max_steps {
+            limits.max_steps = Some(max_steps);
+        }
+
+        match limits.max_time {
+            Some(max_time) if max_time.is_nan() || max_time <= 0.0 => {
+                return Err(SkyError::Config(format!(
+                    "Invalid max_episode_time {}, it must be positive",
+                    max_time
+                )))
+            }
+            _ => {}
+        }
+
+        match limits.max_steps {
+            Some(0) => Err(SkyError::Config(
+                "Invalid max_steps 0, it must be positive".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies any configured agent factions and checks that every
+    /// faction controlled by the agent or a script exists.
+    fn apply_agent_factions(&mut self) -> Result<(), SkyError> {
+        if !self.agent_factions.is_empty() {
+            self.world.write_resource::<AgentFactions>().0 = self.agent_factions.clone();
+        }
+
+        // The observation is from the agent's point of view
+        self.world.write_resource::<Visibility>().observers =
+            self.world.read_resource::<AgentFactions>().0.clone();
+
+        let factions = self.world.read_resource::<Vec<Player>>().len();
+        let agent_factions = self.world.read_resource::<AgentFactions>();
+        let scripted_factions = self.world.read_resource::<ScriptedFactions>();
+
+        let controlled = agent_factions.0.iter().chain(scripted_factions.0.iter());
+        match controlled.cloned().find(|faction| *faction >= factions) {
+            Some(faction) => Err(SkyError::Config(format!(
+                "Faction {} can't be controlled, there are only {} factions",
+                faction, factions
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies any configured overrides on top of the scenario's observation
+    /// grid and feature layers, and sizes everything depending on them to match.
+    fn apply_observation(&mut self) -> Result<(), SkyError> {
+        use self::components::Pos;
+        use ndarray::Array3;
+
+        let mut grid = *self.world.read_resource::<ObservationGrid>();
+
+        if let Some(ref cfg) = self.observation {
+            if let Some(width) = cfg.width {
+                grid.width = width as usize;
+            }
+            if let Some(height) = cfg.height {
+                grid.height = height as usize;
+            }
+            if let Some(cell_size) = cfg.cell_size {
+                grid.cell_size = cell_size;
+            }
+            if let Some(ref origin) = cfg.origin {
+                grid.origin = Pos::new(origin.x, origin.y);
+            }
+        }
+
+        if grid.width == 0 || grid.height == 0 || grid.cell_size.is_nan() || grid.cell_size <= 0.0 {
+            return Err(SkyError::Config(format!(
+                "Invalid observation grid {:?}, the width, height and cell size must be positive",
+                grid
+            )));
+        }
+
+        if !self.feature_layers.is_empty() {
+            *self.world.write_resource::<FeatureLayers>() =
+                FeatureLayers::from_names(&self.feature_layers)?;
+        }
+        let channels = self.world.read_resource::<FeatureLayers>().0.len();
+
+        *self.world.write_resource::<ObservationGrid>() = grid;
+        *self.world.write_resource::<NavGrid>() = NavGrid::for_world(&grid);
+
+        let state = &mut self.world.write_resource::<RtsState>().0;
+        state.features = Array3::zeros([grid.width, grid.height, channels]).into_raw_vec();
+        state.feature_array_dims = vec![grid.width as u32, grid.height as u32, channels as u32];
+
+        Ok(())
+    }
+
+    pub fn reset(&mut self) -> Result<MultiMessage, SkyError> {
+        use scaii_defs::protos;
+
+        self.restart()?;
 
         let mut mm = MultiMessage {
             packets: Vec::with_capacity(2),
@@ -180,8 +429,88 @@ impl<'a, 'b> Rts<'a, 'b> {
         };
 
         mm.packets.push(scaii_packet);
+        mm.packets.extend(self.error_packets());
+
+        Ok(mm)
+    }
+
+    /// Starts a new episode like `reset` without building any messages,
+    /// non-fatal errors are left in `Errors`.
+    pub fn restart(&mut self) -> Result<(), SkyError> {
+        use rand::Isaac64Rng;
+        use util;
+        use shred::RunNow;
+        use self::resources::COLLISION_MARGIN;
+
+        if !self.initialized {
+            self.init()?;
+            self.initialized = true;
+        }
+
+        *self.world.write_resource::<SkyCollisionWorld>() =
+            SkyCollisionWorld::new(COLLISION_MARGIN);
+
+        // An action sent after the last episode ended was never simulated
+        self.world.write_resource::<ActionInput>().0 = None;
+        self.world.delete_all();
+        self.world.write_resource::<NavGrid>().dirty = true;
+        self.world.write_resource::<NeedsKeyInfo>().0 = true;
+        self.world.write_resource::<GameEvents>().0.clear();
+        // Played back episodes start from the recorded seed
+        if let Some(ref player) = self.player {
+            *self.world.write_resource::<SeedRng>() = player.replay.seed.clone();
+            // `Replay::load` makes sure this is at least 1
+            self.world.write_resource::<Episode>().0 = player.replay.episode - 1;
+        }
+        let seed = self.world.read_resource::<SeedRng>().clone();
+        // Do a fast reseed so it doesn't start looping the RNG state
+        // after too many episodes
+        {
+            let rng = &mut *self.world.write_resource::<Isaac64Rng>();
+            let seed_rng = &mut self.world.write_resource::<SeedRng>().0;
+            util::reseed_from(rng, seed_rng);
+
+            self.world.write_resource::<Episode>().0 += 1;
+            self.world.write_resource::<Terminal>().0 = false;
+            *self.world.write_resource::<EpisodeOutcome>() = EpisodeOutcome::default();
+            *self.world.write_resource::<EpisodeClock>() = EpisodeClock::default();
+            // Resized to the observation grid by the `StateBuildSystem`
+            self.world.write_resource::<LastSeen>().0.clear();
 
-        mm
+            let repeat = &mut *self.world.write_resource::<ActionRepeat>();
+            repeat.left = 0;
+        }
+
+        self.lua_sys.reset(&mut self.world)?;
+
+        // Ensure changes and render
+        self.world.maintain();
+        self.sim_systems.dispatch_seq(&self.world.res);
+        self.lua_sys.run_now(&self.world.res);
+        self.lua_sys.apply_changes(&mut self.world);
+        self.out_systems.dispatch_seq(&self.world.res);
+        self.key_info_sent();
+
+        if let Some(ref mut recorder) = self.recorder {
+            let episode = self.world.read_resource::<Episode>().0;
+            let hash = snapshot::hash(&self.world);
+            let res = snapshot::serialize(&self.world, vec![])
+                .map_err(|e| SkyError::Replay(format!("Could not take a snapshot: {}", e)))
+                .and_then(|state| recorder.start(episode, seed, hash, state));
+
+            if let Err(e) = res {
+                self.world.write_resource::<Errors>().0.push(e);
+            }
+        }
+
+        if let Some(ref mut player) = self.player {
+            player.rewind(0);
+            if let Err(e) = player.verify(snapshot::hash(&self.world)) {
+                self.world.write_resource::<Errors>().0.push(e);
+            }
+        }
+
+        Ok(())
     }
 
     pub fn skip(&self) -> bool {
@@ -189,20 +518,157 @@ impl<'a, 'b> Rts<'a, 'b> {
         self.world.read_resource::<Skip>().0
     }
 
-    pub fn update(&mut self) -> MultiMessage {
-        use scaii_defs::protos;
-        use scaii_defs::protos::ScaiiPacket;
+    /// Simulates a single frame without building any messages,
+    /// nothing happens once the episode is over.
+    pub fn tick(&mut self) {
         use specs::RunNow;
 
         if self.world.read_resource::<Terminal>().0 {
-            return Default::default();
+            return;
+        }
+
+        // The replay being played back replaces the agent's actions
+        if let Some(ref mut player) = self.player {
+            match player.next_input() {
+                Some(input) => self.world.write_resource::<ActionInput>().0 = input,
+                None => {
+                    self.world.write_resource::<Terminal>().0 = true;
+                    self.world.write_resource::<RtsState>().0.terminal = true;
+                    self.world.write_resource::<Errors>().0.push(SkyError::Replay(format!(
+                        "The replay ended after {} ticks, before the episode did",
+                        player.len()
+                    )));
+                    return;
+                }
+            }
         }
 
+        let input = match self.recorder {
+            Some(_) => self.world.read_resource::<ActionInput>().0.clone(),
+            None => None,
+        };
+
         self.sim_systems.dispatch_seq(&self.world.res);
         self.lua_sys.run_now(&self.world.res);
+        self.lua_sys.apply_changes(&mut self.world);
         self.out_systems.dispatch_seq(&self.world.res);
+        self.key_info_sent();
 
         self.world.maintain();
+        self.record_tick(input);
+
+        if let Some(ref mut player) = self.player {
+            if let Err(e) = player.verify(snapshot::hash(&self.world)) {
+                self.world.write_resource::<Errors>().0.push(e);
+            }
+        }
+    }
+
+    /// Adds the frame to the replay being recorded, along with a
+    /// snapshot if one is due, writing it out if the episode just ended.
+    fn record_tick(&mut self, input: Option<Action>) {
+        use self::resources::Skip;
+
+        if let Some(ref mut recorder) = self.recorder {
+            let skip = self.world.read_resource::<Skip>().clone();
+            recorder.record(input, skip, snapshot::hash(&self.world));
+
+            if recorder.snapshot_due() {
+                match snapshot::serialize(&self.world, vec![]) {
+                    Ok(state) => recorder.snapshot(state),
+                    Err(e) => self.world.write_resource::<Errors>().0.push(SkyError::Replay(
+                        format!("Could not take a snapshot: {}", e),
+                    )),
+                }
+            }
+
+            if self.world.read_resource::<Terminal>().0 {
+                if let Err(e) = recorder.finish() {
+                    self.world.write_resource::<Errors>().0.push(e);
+                }
+            }
+        }
+    }
+
+    /// Jumps to `tick` frames after the reset in the replay being played
+    /// back, by loading the closest snapshot before it and playing on from
+    /// there. Playback is checked against the replay as usual.
+    pub fn seek(&mut self, tick: usize) -> Result<(), SkyError> {
+        let (start, state) = match self.player {
+            Some(ref player) if tick <= player.len() => match player.snapshot_before(tick) {
+                Some(snapshot) => (snapshot.tick, snapshot.state.clone()),
+                None => {
+                    return Err(SkyError::Replay(
+                        "The replay has no snapshots to seek from".to_string(),
+                    ))
+                }
+            },
+            Some(ref player) => {
+                return Err(SkyError::Replay(format!(
+                    "Can't seek to tick {}, the replay is only {} ticks long",
+                    tick,
+                    player.len()
+                )))
+            }
+            None => return Err(SkyError::Replay("No replay is being played".to_string())),
+        };
+
+        self.deserialize(&state).map_err(|e| {
+            SkyError::Replay(format!("Could not load the snapshot at tick {}: {}", start, e))
+        })?;
+
+        if let Some(ref mut player) = self.player {
+            player.rewind(start);
+        }
+
+        for _ in start..tick {
+            self.tick();
+        }
+
+        Ok(())
+    }
+
+    /// Carries out an action from the agent, simulating its frame and
+    /// any repeated or skipped frames after it.
+    pub fn step(&mut self, action: Action) {
+        self.action_input(action);
+        self.tick();
+
+        while self.skip() && !self.world.read_resource::<Terminal>().0 {
+            self.tick();
+        }
+    }
+
+    /// Carries out an action like `step`, returning the output of the
+    /// last frame simulated along with the errors raised by every frame.
+    ///
+    /// Once the episode is over the action is rejected and the final
+    /// state is sent again, unless a replay is being played back (where
+    /// the action may still seek).
+    pub fn act(&mut self, action: Action) -> MultiMessage {
+        if self.world.read_resource::<Terminal>().0 && self.player.is_none() {
+            self.world.write_resource::<Errors>().0.push(SkyError::InvalidOrder(
+                "The episode is over, the backend has to be reset".to_string(),
+            ));
+        } else {
+            self.step(action);
+        }
+
+        self.output()
+    }
+
+    pub fn update(&mut self) -> MultiMessage {
+        if self.world.read_resource::<Terminal>().0 {
+            return Default::default();
+        }
+
+        self.tick();
+        self.output()
+    }
+
+    /// The render, state and error packets for the last frame simulated.
+    fn output(&mut self) -> MultiMessage {
+        use scaii_defs::protos;
 
         let mut packets = vec![];
         if self.render {
@@ -240,11 +706,76 @@ impl<'a, 'b> Rts<'a, 'b> {
         };
 
         packets.push(state_packet);
+        packets.extend(self.error_packets());
 
         MultiMessage { packets: packets }
     }
 
+    /// Once a frame has actually been output, later
+    /// frames only need to send deltas.
+    fn key_info_sent(&mut self) {
+        if !self.skip() {
+            self.world.write_resource::<NeedsKeyInfo>().0 = false;
+        }
+    }
+
+    /// Drains any errors raised by systems during the last frame
+    /// into packets to be reported to the core.
+    fn error_packets(&mut self) -> Vec<ScaiiPacket> {
+        use util;
+
+        self.world
+            .write_resource::<Errors>()
+            .0
+            .drain(..)
+            .map(|e| util::error_packet(&e))
+            .collect()
+    }
+
+    /// Serializes the current state of the simulation, reusing
+    /// the provided buffer if there is one.
+    pub fn serialize(&mut self, buf: Option<Vec<u8>>) -> Result<Vec<u8>, Box<Error>> {
+        snapshot::serialize(&self.world, buf.unwrap_or_default())
+    }
+
+    /// Restores a state previously produced by `serialize`,
+    /// loading the scenario first if needed.
+    pub fn deserialize(&mut self, buf: &[u8]) -> Result<(), Box<Error>> {
+        if !self.initialized {
+            self.init()?;
+            self.initialized = true;
+        }
+
+        snapshot::deserialize(&mut self.world, buf)?;
+        self.world.write_resource::<NeedsKeyInfo>().0 = true;
+        self.world.write_resource::<GameEvents>().0.clear();
+
+        Ok(())
+    }
+
+    /// Queues the agent's action for the next frame. While a replay is
+    /// played back the action is only checked for a `seek`.
     pub fn action_input(&mut self, action: Action) {
+        use prost::Message;
+        use protos::ActionList;
+
+        if self.player.is_some() {
+            let seek = match action.alternate_actions {
+                Some(ref buf) => ActionList::decode(&buf[..]).map(|list| list.seek),
+                None => Ok(None),
+            };
+
+            let res = match seek {
+                Ok(Some(tick)) => self.seek(tick as usize),
+                Ok(None) => Ok(()),
+                Err(e) => Err(SkyError::from(e)),
+            };
+
+            if let Err(e) = res {
+                self.world.write_resource::<Errors>().0.push(e);
+            }
+        }
+
         self.world.write_resource::<ActionInput>().0 = Some(action);
     }
 }
@@ -252,6 +783,8 @@ impl<'a, 'b> Rts<'a, 'b> {
 #[cfg(test)]
 mod tests {
     use super::{Player, Rts};
+    use super::test_util::{action, error_count, inline_rts, move_to, state_update};
+    use scaii_defs::protos::Action;
     use std::path::PathBuf;
 
     #[test]
@@ -262,10 +795,223 @@ mod tests {
             env!("CARGO_MANIFEST_DIR")
         )));
 
-        rts.init();
+        rts.init().unwrap();
 
         assert!(rts.world.read_resource::<Vec<Player>>().len() == 2);
 
-        let _mm = rts.reset();
+        let _mm = rts.reset().unwrap();
+    }
+
+    #[test]
+    fn serialize_roundtrip() {
+        use super::components::{Hp, Pos};
+        use specs::Join;
+
+        let mut rts = Rts::new();
+        rts.lua_path = Some(PathBuf::from(format!(
+            "{}/lua/tower_example.lua",
+            env!("CARGO_MANIFEST_DIR")
+        )));
+
+        rts.reset().unwrap();
+        rts.update();
+
+        let buf = rts.serialize(None).unwrap();
+
+        let snapshot = |rts: &Rts| {
+            let mut units: Vec<_> = (&rts.world.read::<Pos>(), &rts.world.read::<Hp>())
+                .join()
+                .map(|(pos, hp)| (pos.x, pos.y, hp.curr_hp))
+                .collect();
+            units.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            units
+        };
+        let before = snapshot(&rts);
+
+        rts.reset().unwrap();
+        rts.deserialize(&buf).unwrap();
+
+        assert_eq!(before, snapshot(&rts));
+    }
+
+    #[test]
+    fn seeded_reset_is_reproducible() {
+        use super::components::Pos;
+        use specs::Join;
+
+        let positions = || {
+            let mut rts = Rts::new();
+            rts.lua_path = Some(PathBuf::from(format!(
+                "{}/lua/tower_example.lua",
+                env!("CARGO_MANIFEST_DIR")
+            )));
+            rts.seed(&[1, 2, 3]);
+
+            rts.reset().unwrap();
+            rts.reset().unwrap();
+
+            let positions: Vec<_> = rts.world
+                .read::<Pos>()
+                .join()
+                .map(|pos| (pos.x, pos.y))
+                .collect();
+            positions
+        };
+
+        assert_eq!(positions(), positions());
+    }
+
+    #[test]
+    fn raster_matches_collision_queries() {
+        use nalgebra::Point2;
+        use ncollide::world::CollisionGroups;
+        use engine::resources::{ObservationGrid, RtsState, SkyCollisionWorld, COLLISION_SCALE,
+                                MAX_FACTIONS};
+
+        let mut rts = Rts::new();
+        rts.lua_path = Some(PathBuf::from(format!(
+            "{}/lua/tower_example.lua",
+            env!("CARGO_MANIFEST_DIR")
+        )));
+
+        rts.reset().unwrap();
+        for _ in 0..10 {
+            rts.update();
+        }
+
+        let grid = *rts.world.read_resource::<ObservationGrid>();
+        let state = rts.world.read_resource::<RtsState>();
+        let c_world = rts.world.read_resource::<SkyCollisionWorld>();
+
+        let mut c_group = CollisionGroups::new();
+        for i in 0..MAX_FACTIONS {
+            c_group.modify_membership(i, true);
+        }
+
+        for i in 0..grid.width {
+            for j in 0..grid.height {
+                let sample = grid.sample_point(i, j);
+                let pt = Point2::new(sample.x / COLLISION_SCALE, sample.y / COLLISION_SCALE);
+                let hits: Vec<_> = c_world
+                    .interferences_with_point(&pt, &c_group)
+                    .filter(|v| !v.data().detector)
+                    .map(|v| (v.data().e.id() + 1) as f64)
+                    .collect();
+
+                // Which overlapping unit a query reports is unspecified
+                if hits.len() > 1 {
+                    continue;
+                }
+
+                let expected = hits.first().cloned().unwrap_or(0.0);
+                assert_eq!(state.0.features[(i * grid.height + j) * 4], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn rejected_orders_are_reported_after_skipped_frames() {
+        use protos::ActionList;
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 1, pos = { x = 400.0, y = 400.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+        let mm = rts.act(action(ActionList {
+            actions: vec![move_to(0, 300.0, 400.0)],
+            skip: Some(true),
+            ..Default::default()
+        }));
+
+        assert_eq!(error_count(&mm), 1);
+    }
+
+    #[test]
+    fn step_limit_truncates_the_episode() {
+        use super::resources::{EpisodeOutcome, Outcome, RtsState, Terminal};
+
+        // `on_timeout` picks the winner, otherwise it would be a draw
+        let mut rts = inline_rts(
+            "max_steps = 3,",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            "#,
+            "function on_timeout(world) world:victory(1) end",
+        );
+
+        rts.reset().unwrap();
+        rts.step(Action::default());
+        assert!(!rts.world.read_resource::<Terminal>().0);
+
+        rts.step(Action::default());
+        assert!(rts.world.read_resource::<Terminal>().0);
+
+        let outcome = *rts.world.read_resource::<EpisodeOutcome>();
+        assert_eq!(outcome.outcome, Some(Outcome::Victory(1)));
+        assert!(outcome.truncated);
+
+        assert!(!rts.world.read_resource::<RtsState>().0.terminal);
+
+        let outcome = state_update(&rts).outcome.unwrap();
+        assert_eq!(outcome.winner, Some(1));
+        assert_eq!(outcome.truncated, Some(true));
+    }
+
+    #[test]
+    fn zero_step_limit_is_rejected() {
+        let mut rts = inline_rts("", "", "");
+        rts.max_steps = Some(0);
+
+        assert!(rts.reset().is_err());
+    }
+
+    #[test]
+    fn acting_stops_skipping_when_the_episode_ends() {
+        use super::resources::Terminal;
+        use protos::ActionList;
+        use scaii_defs::protos::scaii_packet::SpecificMsg;
+
+        let mut rts = inline_rts(
+            "max_steps = 5,",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            "#,
+            "",
+        );
+
+        // Skips forever, so only the time limit can stop it
+        rts.reset().unwrap();
+        let mm = rts.act(action(ActionList {
+            skip: Some(true),
+            skip_lua: Some("true".to_string()),
+            ..Default::default()
+        }));
+
+        let states = mm.packets.iter().filter(|packet| match packet.specific_msg {
+            Some(SpecificMsg::State(_)) => true,
+            _ => false,
+        });
+        assert_eq!(states.count(), 1);
+        assert!(rts.world.read_resource::<Terminal>().0);
+
+        // Acting on the finished episode resends its state with an error
+        let mm = rts.act(Action::default());
+        let kinds: Vec<_> = mm.packets
+            .iter()
+            .filter_map(|packet| match packet.specific_msg {
+                Some(SpecificMsg::State(_)) => Some("state"),
+                Some(SpecificMsg::Err(ref e)) => {
+                    assert_eq!(e.fatal, Some(false));
+                    Some("error")
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(kinds, vec!["state", "error"]);
     }
 }
@@ -0,0 +1,454 @@
+use scaii_defs::protos::Action;
+
+use engine::resources::{SeedRng, Skip};
+use error::SkyError;
+use protos::Config;
+
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the replay format changes, so old
+/// replays are rejected instead of misread.
+pub const REPLAY_VERSION: u32 = 2;
+
+/// How many frames apart the snapshots used for seeking are taken.
+pub const SNAPSHOT_INTERVAL: usize = 600;
+
+/// The scenario a replay was recorded with, so playback can
+/// tell if the script has changed since.
+#[derive(Clone, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub struct ReplayScenario {
+    /// `None` for inline scenario sources
+    pub path: Option<PathBuf>,
+    /// The FNV-1a hash of the script's source
+    pub hash: u64,
+}
+
+impl ReplayScenario {
+    /// Fails with a non-fatal `SkyError::Replay` if the script can't be read.
+    pub fn new(source: &Option<String>, path: &Option<PathBuf>) -> Result<Self, SkyError> {
+        use util;
+        use std::fs::File;
+        use std::io::Read;
+
+        match (source, path) {
+            (&Some(ref src), _) => Ok(ReplayScenario {
+                path: None,
+                hash: util::fnv1a(src.as_bytes()),
+            }),
+            (&None, &Some(ref path)) => {
+                let mut src = vec![];
+                File::open(path)
+                    .and_then(|mut file| file.read_to_end(&mut src))
+                    .map_err(|e| {
+                        SkyError::Replay(format!(
+                            "Could not read the scenario {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })?;
+
+                Ok(ReplayScenario {
+                    path: Some(path.clone()),
+                    hash: util::fnv1a(&src),
+                })
+            }
+            (&None, &None) => Ok(Default::default()),
+        }
+    }
+}
+
+/// A single simulated frame.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ReplayTick {
+    /// The protobuf encoded `Action` given to the `InputSystem`
+    /// this frame, `None` between actions
+    pub action: Option<Vec<u8>>,
+    /// The skip flags after the frame
+    pub skip: Skip,
+    /// The world's hash after the frame, see `snapshot::hash`
+    pub hash: u64,
+}
+
+/// The serialized world after `tick` frames, for seeking.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ReplaySnapshot {
+    pub tick: usize,
+    pub state: Vec<u8>,
+}
+
+/// Everything needed to play an episode back offline.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub scenario: ReplayScenario,
+    /// The protobuf encoded `Config` the episode was run with
+    pub config: Vec<u8>,
+    pub episode: usize,
+    /// The generator the episode's RNG was reseeded from on reset
+    pub seed: SeedRng,
+    /// The world's hash right after the reset
+    pub start_hash: u64,
+    /// Every frame after the one simulated on reset
+    pub ticks: Vec<ReplayTick>,
+    /// Taken every `SNAPSHOT_INTERVAL` frames, starting with the reset
+    pub snapshots: Vec<ReplaySnapshot>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, SkyError> {
+        use bincode::{self, Infinite};
+        use std::fs::File;
+        use std::io::BufReader;
+
+        let read = || -> Result<Replay, Box<::std::error::Error>> {
+            let mut file = BufReader::new(File::open(path)?);
+            Ok(bincode::deserialize_from(&mut file, Infinite)?)
+        };
+
+        let replay = read().map_err(|e| {
+            SkyError::Config(format!("Could not read replay {}: {}", path.display(), e))
+        })?;
+
+        if replay.version != REPLAY_VERSION {
+            return Err(SkyError::Config(format!(
+                "Replay {} is version {}, only version {} is supported",
+                path.display(),
+                replay.version,
+                REPLAY_VERSION
+            )));
+        }
+
+        if replay.episode == 0 {
+            return Err(SkyError::Config(format!(
+                "Replay {} is for episode 0, episodes start at 1",
+                path.display()
+            )));
+        }
+
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SkyError> {
+        use bincode::{self, Infinite};
+        use std::fs::{self, File};
+        use std::io::BufWriter;
+
+        let write = || -> Result<(), Box<::std::error::Error>> {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            let mut file = BufWriter::new(File::create(path)?);
+            bincode::serialize_into(&mut file, self, Infinite)?;
+            Ok(())
+        };
+
+        write().map_err(|e| {
+            SkyError::Replay(format!("Could not write {}: {}", path.display(), e))
+        })
+    }
+}
+
+/// Records each episode as it's played and writes it to
+/// `<dir>/episode_<n>.replay` once it ends.
+pub struct ReplayRecorder {
+    pub dir: PathBuf,
+    pub scenario: ReplayScenario,
+    config: Vec<u8>,
+    current: Option<Replay>,
+}
+
+impl ReplayRecorder {
+    pub fn new(dir: PathBuf, cfg: &Config) -> Self {
+        use prost::Message;
+
+        let mut config = Vec::with_capacity(cfg.encoded_len());
+        cfg.encode(&mut config).unwrap();
+
+        ReplayRecorder {
+            dir,
+            scenario: Default::default(),
+            config,
+            current: None,
+        }
+    }
+
+    /// Begins recording a new episode from the world just after its
+    /// reset, writing out the previous one if it was cut off before
+    /// it ended.
+    pub fn start(
+        &mut self,
+        episode: usize,
+        seed: SeedRng,
+        hash: u64,
+        state: Vec<u8>,
+    ) -> Result<(), SkyError> {
+        let res = self.finish();
+
+        self.current = Some(Replay {
+            version: REPLAY_VERSION,
+            scenario: self.scenario.clone(),
+            config: self.config.clone(),
+            episode,
+            seed,
+            start_hash: hash,
+            ticks: vec![],
+            snapshots: vec![ReplaySnapshot { tick: 0, state }],
+        });
+
+        res
+    }
+
+    pub fn record(&mut self, action: Option<Action>, skip: Skip, hash: u64) {
+        use prost::Message;
+
+        if let Some(ref mut replay) = self.current {
+            let action = action.map(|action| {
+                let mut buf = Vec::with_capacity(action.encoded_len());
+                action.encode(&mut buf).unwrap();
+                buf
+            });
+
+            replay.ticks.push(ReplayTick { action, skip, hash });
+        }
+    }
+
+    /// Whether the frame just recorded should be followed by a snapshot.
+    pub fn snapshot_due(&self) -> bool {
+        match self.current {
+            Some(ref replay) => replay.ticks.len() % SNAPSHOT_INTERVAL == 0,
+            None => false,
+        }
+    }
+
+    pub fn snapshot(&mut self, state: Vec<u8>) {
+        if let Some(ref mut replay) = self.current {
+            let tick = replay.ticks.len();
+            replay.snapshots.push(ReplaySnapshot { tick, state });
+        }
+    }
+
+    /// Writes out the current episode, if there is one.
+    pub fn finish(&mut self) -> Result<(), SkyError> {
+        match self.current.take() {
+            Some(replay) => {
+                let path = self.dir.join(format!("episode_{}.replay", replay.episode));
+                replay.save(&path)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ReplayRecorder {
+    fn drop(&mut self) {
+        // Nowhere left to report this
+        let _ = self.finish();
+    }
+}
+
+/// Plays a recorded episode back, feeding its actions to the
+/// `InputSystem` and checking each frame against the recorded hashes.
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    inputs: Vec<Option<Action>>,
+    /// How many frames have been played since the reset
+    pub tick: usize,
+    /// The first frame that didn't match the replay, if any
+    pub diverged_at: Option<usize>,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Result<Self, SkyError> {
+        use prost::Message;
+
+        let mut inputs = Vec::with_capacity(replay.ticks.len());
+        for tick in &replay.ticks {
+            inputs.push(match tick.action {
+                Some(ref buf) => Some(Action::decode(&buf[..])?),
+                None => None,
+            });
+        }
+
+        Ok(ReplayPlayer {
+            replay,
+            inputs,
+            tick: 0,
+            diverged_at: None,
+        })
+    }
+
+    /// The number of frames recorded after the reset.
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Starts playing from `tick` frames in, from a recorded state.
+    pub fn rewind(&mut self, tick: usize) {
+        self.tick = tick;
+        self.diverged_at = None;
+    }
+
+    /// The input for the next frame, or `None` once the replay is over.
+    pub fn next_input(&mut self) -> Option<Option<Action>> {
+        let input = self.inputs.get(self.tick).cloned();
+        if input.is_some() {
+            self.tick += 1;
+        }
+
+        input
+    }
+
+    /// The latest snapshot taken at or before `tick`.
+    pub fn snapshot_before(&self, tick: usize) -> Option<&ReplaySnapshot> {
+        self.replay
+            .snapshots
+            .iter()
+            .take_while(|snapshot| snapshot.tick <= tick)
+            .last()
+    }
+
+    /// Checks the world's hash against the one recorded after the
+    /// frame just played, only the first mismatch is reported.
+    pub fn verify(&mut self, hash: u64) -> Result<(), SkyError> {
+        let expected = match self.tick {
+            0 => self.replay.start_hash,
+            tick => self.replay.ticks[tick - 1].hash,
+        };
+
+        if hash == expected || self.diverged_at.is_some() {
+            return Ok(());
+        }
+
+        self.diverged_at = Some(self.tick);
+        Err(SkyError::Replay(format!(
+            "Playback stopped matching episode {} of the replay at tick {}",
+            self.replay.episode, self.tick
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReplayRecorder;
+    use engine::test_util::{action, scenario_rts};
+    use protos::Config;
+    use scaii_defs::protos::Action;
+
+    #[test]
+    fn records_replay() {
+        use std::env;
+        use std::fs;
+
+        let dir = env::temp_dir().join("sky_rts_records_replay");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut rts = scenario_rts("tower_example");
+        rts.recorder = Some(ReplayRecorder::new(dir.clone(), &Config::default()));
+
+        rts.reset().unwrap();
+        rts.step(Action::default());
+        rts.reset().unwrap();
+
+        // The first episode is written out when it's cut off by the reset
+        assert!(dir.join("episode_1.replay").exists());
+        assert!(!dir.join("episode_2.replay").exists());
+
+        rts.recorder = None;
+        assert!(dir.join("episode_2.replay").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_replay_writes_are_not_fatal() {
+        use engine::resources::Errors;
+        use error::SkyError;
+        use std::env;
+        use std::fs::{self, File};
+
+        // A file in the way of the replay directory
+        let file = env::temp_dir().join("sky_rts_unwritable_replay");
+        File::create(&file).unwrap();
+
+        let mut rts = scenario_rts("tower_example");
+        rts.recorder = Some(ReplayRecorder::new(file.join("replays"), &Config::default()));
+
+        rts.reset().unwrap();
+        rts.step(Action::default());
+        rts.restart().unwrap();
+
+        {
+            let errors = rts.world.read_resource::<Errors>();
+            assert_eq!(errors.0.len(), 1);
+            match errors.0[0] {
+                SkyError::Replay(_) => {}
+                ref e => panic!("Unexpected error {:?}", e),
+            }
+        }
+
+        rts.recorder = None;
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn replay_playback_matches_recording() {
+        use super::{Replay, ReplayPlayer};
+        use protos::ActionList;
+        use std::env;
+        use std::fs;
+
+        let dir = env::temp_dir().join("sky_rts_replay_playback");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut rts = scenario_rts("tower_example");
+        rts.seed(&[4, 2]);
+        rts.recorder = Some(ReplayRecorder::new(dir.clone(), &Config::default()));
+
+        rts.reset().unwrap();
+        for _ in 0..20 {
+            rts.step(Action::default());
+        }
+        rts.recorder = None;
+
+        let replay = Replay::load(&dir.join("episode_1.replay")).unwrap();
+
+        let mut playback = scenario_rts("tower_example");
+        playback.player = Some(ReplayPlayer::new(replay).unwrap());
+
+        playback.reset().unwrap();
+        for _ in 0..20 {
+            playback.step(Action::default());
+        }
+        assert_eq!(playback.player.as_ref().unwrap().diverged_at, None);
+
+        playback.seek(5).unwrap();
+        {
+            let player = playback.player.as_ref().unwrap();
+            assert_eq!(player.tick, 5);
+            assert_eq!(player.diverged_at, None);
+        }
+
+        // Agents seek through the action, before its frame is played
+        playback.step(action(ActionList {
+            seek: Some(10),
+            ..Default::default()
+        }));
+        {
+            let player = playback.player.as_ref().unwrap();
+            assert_eq!(player.tick, 11);
+            assert_eq!(player.diverged_at, None);
+        }
+
+        let mut replay = Replay::load(&dir.join("episode_1.replay")).unwrap();
+        replay.episode = 0;
+        replay.save(&dir.join("episode_0.replay")).unwrap();
+        assert!(Replay::load(&dir.join("episode_0.replay")).unwrap_err().is_fatal());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,10 @@
-use nalgebra::{Isometry2, Point2};
+use nalgebra::{Isometry2, Point2, Vector2};
 use ncollide::world::CollisionWorld;
 use specs::Entity;
 
+use engine::components::Shape;
+use super::COLLISION_SCALE;
+
 #[derive(Debug)]
 pub struct ColliderData {
     pub e: Entity,
@@ -9,3 +12,29 @@ pub struct ColliderData {
 }
 
 pub type SkyCollisionWorld = CollisionWorld<Point2<f64>, Isometry2<f64>, ColliderData>;
+
+/// The half width and half height of the collider used for `shape`,
+/// in collision world units.
+///
+/// Triangles use a 2D `Cylinder`, which is actually a box of half
+/// width `radius` and half height `half_height`.
+pub fn collider_half_extents(shape: &Shape) -> Vector2<f64> {
+    match *shape {
+        Shape::Rect { width, height } => {
+            let width = width / COLLISION_SCALE;
+            let height = height / COLLISION_SCALE;
+
+            // ncollide likes half widths and heights, so divide by 2
+            Vector2::new(width / 2.0, height / 2.0)
+        }
+        Shape::Triangle { base_len } => {
+            let base_len = base_len / COLLISION_SCALE;
+
+            // equilateral triangle dimensions
+            let half_height = base_len / (2.0 as f64).sqrt() / 2.0;
+            let radius = base_len / 2.0;
+
+            Vector2::new(radius, half_height)
+        }
+    }
+}
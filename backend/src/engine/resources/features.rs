@@ -0,0 +1,222 @@
+use error::SkyError;
+
+use super::{UnitType, UnitTypeMap};
+
+/// A single channel of the observation, computed for whichever
+/// unit covers each cell (empty cells are always 0).
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FeatureLayer {
+    /// The unit's entity ID, offset by 1
+    UnitId,
+    Hp,
+    MaxHp,
+    /// Current HP divided by max HP
+    HpFraction,
+    /// The unit type's ID, offset by 1
+    UnitType,
+    /// The owning faction, offset by 1
+    Faction,
+    /// How far along the unit is toward its next attack, from 0 to 1.
+    /// Units that aren't attacking are always ready (1).
+    AttackCooldown,
+    Speed,
+    /// 1 for units that can move, 0 for static ones
+    Movable,
+    /// 1 if the unit has a move order (including attack moves)
+    HasMoveOrder,
+    /// The entity ID of the unit's attack target offset by 1,
+    /// or 0 if it isn't attacking
+    AttackTarget,
+    /// A custom attribute from the unit type's `attributes` table in Lua,
+    /// 0 for unit types that don't define it
+    Attribute(String),
+    /// 1 for cells any of the agent's factions can currently see
+    Visible,
+    /// The unit type ID (offset by 1) of the enemy last seen in each cell,
+    /// remembered until the cell is seen again
+    LastSeen,
+}
+
+/// The prefix used to name `FeatureLayer::Attribute` layers,
+/// e.g. `attribute:armor`.
+pub const ATTRIBUTE_PREFIX: &str = "attribute:";
+
+impl FeatureLayer {
+    /// Parses the layer's name, as used by `sky_init` and the `Config`.
+    pub fn from_name(name: &str) -> Result<Self, SkyError> {
+        Ok(match name {
+            "unit_id" => FeatureLayer::UnitId,
+            "hp" => FeatureLayer::Hp,
+            "max_hp" => FeatureLayer::MaxHp,
+            "hp_fraction" => FeatureLayer::HpFraction,
+            "unit_type" => FeatureLayer::UnitType,
+            "faction" => FeatureLayer::Faction,
+            "attack_cooldown" => FeatureLayer::AttackCooldown,
+            "speed" => FeatureLayer::Speed,
+            "movable" => FeatureLayer::Movable,
+            "has_move_order" => FeatureLayer::HasMoveOrder,
+            "attack_target" => FeatureLayer::AttackTarget,
+            "visible" => FeatureLayer::Visible,
+            "last_seen" => FeatureLayer::LastSeen,
+            _ if name.starts_with(ATTRIBUTE_PREFIX) && name.len() > ATTRIBUTE_PREFIX.len() => {
+                FeatureLayer::Attribute(name[ATTRIBUTE_PREFIX.len()..].to_string())
+            }
+            _ => {
+                return Err(SkyError::Config(format!(
+                    "Unknown feature layer {:?}",
+                    name
+                )))
+            }
+        })
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            FeatureLayer::UnitId => "unit_id".to_string(),
+            FeatureLayer::Hp => "hp".to_string(),
+            FeatureLayer::MaxHp => "max_hp".to_string(),
+            FeatureLayer::HpFraction => "hp_fraction".to_string(),
+            FeatureLayer::UnitType => "unit_type".to_string(),
+            FeatureLayer::Faction => "faction".to_string(),
+            FeatureLayer::AttackCooldown => "attack_cooldown".to_string(),
+            FeatureLayer::Speed => "speed".to_string(),
+            FeatureLayer::Movable => "movable".to_string(),
+            FeatureLayer::HasMoveOrder => "has_move_order".to_string(),
+            FeatureLayer::AttackTarget => "attack_target".to_string(),
+            FeatureLayer::Attribute(ref attr) => format!("{}{}", ATTRIBUTE_PREFIX, attr),
+            FeatureLayer::Visible => "visible".to_string(),
+            FeatureLayer::LastSeen => "last_seen".to_string(),
+        }
+    }
+
+    /// Whether the layer's values are IDs rather than magnitudes,
+    /// categorical layers should be one-hot encoded rather than scaled.
+    pub fn is_categorical(&self) -> bool {
+        match *self {
+            FeatureLayer::UnitId
+            | FeatureLayer::UnitType
+            | FeatureLayer::Faction
+            | FeatureLayer::AttackTarget
+            | FeatureLayer::LastSeen => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the layer describes cells rather than the units in them,
+    /// so it's filled in for empty cells too.
+    pub fn is_per_cell(&self) -> bool {
+        match *self {
+            FeatureLayer::Visible | FeatureLayer::LastSeen => true,
+            _ => false,
+        }
+    }
+
+    /// The largest value the layer can take in this scenario,
+    /// dividing by it scales the layer into `[0, 1]`.
+    ///
+    /// This is `None` for layers without a meaningful bound (entity IDs),
+    /// or when no unit type gives the layer a positive value.
+    pub fn max_value(&self, unit_types: &UnitTypeMap, factions: usize) -> Option<f64> {
+        match *self {
+            FeatureLayer::UnitId | FeatureLayer::AttackTarget => None,
+            FeatureLayer::Hp | FeatureLayer::MaxHp => max_over(unit_types, |u_type| u_type.max_hp),
+            FeatureLayer::Speed => max_over(unit_types, |u_type| {
+                if u_type.movable {
+                    u_type.speed
+                } else {
+                    0.0
+                }
+            }),
+            FeatureLayer::Attribute(ref attr) => max_over(unit_types, |u_type| {
+                u_type.attributes.get(attr).cloned().unwrap_or(0.0).abs()
+            }),
+            FeatureLayer::UnitType | FeatureLayer::LastSeen => {
+                Some(unit_types.typ_ids.len() as f64)
+            }
+            FeatureLayer::Faction => Some(factions as f64),
+            FeatureLayer::HpFraction
+            | FeatureLayer::AttackCooldown
+            | FeatureLayer::Movable
+            | FeatureLayer::HasMoveOrder
+            | FeatureLayer::Visible => Some(1.0),
+        }
+    }
+}
+
+fn max_over<F: Fn(&UnitType) -> f64>(unit_types: &UnitTypeMap, f: F) -> Option<f64> {
+    let max = unit_types
+        .tag_map
+        .values()
+        .map(f)
+        .fold(0.0, |acc: f64, v| acc.max(v));
+
+    if max > 0.0 {
+        Some(max)
+    } else {
+        None
+    }
+}
+
+/// The layers making up the observation, in channel order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FeatureLayers(pub Vec<FeatureLayer>);
+
+impl FeatureLayers {
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, SkyError> {
+        if names.is_empty() {
+            return Err(SkyError::Config(
+                "At least one feature layer is required".to_string(),
+            ));
+        }
+
+        names
+            .iter()
+            .map(|name| FeatureLayer::from_name(name.as_ref()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(FeatureLayers)
+    }
+}
+
+impl Default for FeatureLayers {
+    /// Unit ID, HP, unit type and faction.
+    fn default() -> Self {
+        FeatureLayers(vec![
+            FeatureLayer::UnitId,
+            FeatureLayer::Hp,
+            FeatureLayer::UnitType,
+            FeatureLayer::Faction,
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FeatureLayer, FeatureLayers};
+
+    #[test]
+    fn layer_names_roundtrip() {
+        let names = [
+            "unit_id",
+            "hp",
+            "max_hp",
+            "hp_fraction",
+            "unit_type",
+            "faction",
+            "attack_cooldown",
+            "speed",
+            "movable",
+            "has_move_order",
+            "attack_target",
+            "attribute:armor",
+            "visible",
+            "last_seen",
+        ];
+
+        let layers = FeatureLayers::from_names(&names).unwrap();
+        let roundtrip: Vec<_> = layers.0.iter().map(FeatureLayer::name).collect();
+
+        assert_eq!(roundtrip, names);
+        assert!(FeatureLayer::from_name("attribute:").is_err());
+        assert!(FeatureLayer::from_name("mana").is_err());
+    }
+}
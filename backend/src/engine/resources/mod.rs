@@ -1,15 +1,23 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::FactionId;
-use super::components::{AttackSensor, CollisionHandle, Color, Hp, Pos, Shape};
+use super::components::{AttackSensor, CollisionHandle, Color, Hp, MoveBehavior, Pos, Shape};
 
 use scaii_defs::protos::{Action, State, Viz};
 
+use rand::Isaac64Rng;
+
+use error::SkyError;
+
 use specs::{Entity, World, WriteStorage};
 
 pub mod collision;
+pub mod features;
+pub mod nav;
 
 pub use self::collision::*;
+pub use self::features::*;
+pub use self::nav::*;
 
 // Recommended by ncollide
 pub const COLLISION_MARGIN: f64 = 0.02;
@@ -19,6 +27,7 @@ pub const COLLISION_SCALE: f64 = 30.0;
 
 pub const MAX_FACTIONS: usize = 15;
 
+// Default observation grid, sampling a 500x500 world
 pub const STATE_SIZE: usize = 100;
 pub const STATE_SCALE: usize = 5;
 
@@ -43,9 +52,15 @@ pub(super) fn register_world_resources(world: &mut World) {
 
     let rng = util::make_rng();
     world.add_resource(rng);
+    world.add_resource(SeedRng(util::make_rng()));
     world.add_resource(Episode(0));
     world.add_resource(Terminal(false));
+    world.add_resource(EpisodeOutcome::default());
+    world.add_resource(EpisodeLimits::default());
+    world.add_resource(EpisodeClock::default());
+    world.add_resource(OutcomeRewards::default());
     world.add_resource(DeltaT(SIXTY_FPS));
+    world.add_resource(ActionRepeat::default());
     world.add_resource(Render::default());
     world.add_resource(NeedsKeyInfo(true));
     world.add_resource::<Vec<Player>>(Vec::new());
@@ -53,6 +68,12 @@ pub(super) fn register_world_resources(world: &mut World) {
     world.add_resource(U64MarkerAllocator::new());
     world.add_resource(ActionInput::default());
     world.add_resource(SkyCollisionWorld::new(COLLISION_MARGIN));
+    world.add_resource(ObservationGrid::default());
+    world.add_resource(FeatureLayers::default());
+    world.add_resource(Visibility::default());
+    world.add_resource(LastSeen::default());
+    world.add_resource(AgentFactions::default());
+    world.add_resource(ScriptedFactions::default());
     world.add_resource(RtsState(State {
         features: Array3::zeros([STATE_SIZE, STATE_SIZE, 4]).into_raw_vec(),
         feature_array_dims: vec![STATE_SIZE as u32, STATE_SIZE as u32, 4],
@@ -60,11 +81,19 @@ pub(super) fn register_world_resources(world: &mut World) {
     }));
     world.add_resource(Reward::default());
     world.add_resource(Skip(false, None));
+    world.add_resource(Errors::default());
+    world.add_resource(GameEvents::default());
+    world.add_resource(NavGrid::default());
 }
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct RtsState(pub State);
 
+/// The generator each episode's RNG is reseeded from on reset,
+/// seeding this makes a sequence of episodes reproducible.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SeedRng(pub Isaac64Rng);
+
 /// The current episode, only meaningful for sequential runs.
 #[derive(Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct Episode(pub usize);
@@ -73,10 +102,184 @@ pub struct Episode(pub usize);
 #[derive(Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct Terminal(pub bool);
 
-/// Time since the last update, in seconds (fixed to one sixtieth of a second for our purposes).
+/// How an episode ended.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Outcome {
+    /// The faction won
+    Victory(usize),
+    Draw,
+}
+
+/// How the current episode ended, if it has.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default, Debug, Serialize, Deserialize)]
+pub struct EpisodeOutcome {
+    /// `None` until the episode is over
+    pub outcome: Option<Outcome>,
+    /// Whether the episode was cut off by its time limit,
+    /// rather than ending on its own
+    pub truncated: bool,
+}
+
+/// Limits on how long an episode may run before it's
+/// truncated, from the scenario or the `Config`.
+#[derive(Copy, Clone, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub struct EpisodeLimits {
+    /// In simulated seconds
+    pub max_time: Option<f64>,
+    /// In simulated frames
+    pub max_steps: Option<u64>,
+}
+
+impl EpisodeLimits {
+    pub fn reached(&self, clock: &EpisodeClock) -> bool {
+        self.max_time.map_or(false, |max_time| clock.time >= max_time)
+            || self.max_steps.map_or(false, |max_steps| clock.steps >= max_steps)
+    }
+}
+
+/// How long the current episode has been running, counting the
+/// frame simulated on reset.
+#[derive(Copy, Clone, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub struct EpisodeClock {
+    pub time: f64,
+    pub steps: u64,
+}
+
+/// The `victory` and `failure` rewards given to the agent when one
+/// of its factions wins or loses, from `victory_reward` and
+/// `failure_penalty` in the scenario.
+#[derive(Copy, Clone, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub struct OutcomeRewards {
+    pub victory: f64,
+    pub failure: f64,
+}
+
+/// Time since the last update, in seconds (one sixtieth of a second
+/// unless the `Config` sets a `tick_length`).
 #[derive(Copy, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct DeltaT(pub f64);
 
+/// How many ticks are simulated for each action from the agent,
+/// only the last of which is output.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ActionRepeat {
+    pub ticks: usize,
+    /// The ticks still to be simulated for the current action
+    pub left: usize,
+    /// The agent's own skip settings, which take over after the last tick
+    pub then: Skip,
+}
+
+impl Default for ActionRepeat {
+    fn default() -> Self {
+        ActionRepeat {
+            ticks: 1,
+            left: 0,
+            then: Skip(false, None),
+        }
+    }
+}
+
+/// The region of the world rasterized into the observation,
+/// this also serves as the extent of the world for pathfinding.
+///
+/// Cell `(i, j)` samples the world at `origin + (i, j) * cell_size`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ObservationGrid {
+    pub width: usize,
+    pub height: usize,
+    pub cell_size: f64,
+    pub origin: Pos,
+}
+
+impl ObservationGrid {
+    pub fn world_width(&self) -> f64 {
+        self.width as f64 * self.cell_size
+    }
+
+    pub fn world_height(&self) -> f64 {
+        self.height as f64 * self.cell_size
+    }
+
+    /// The world position sampled by cell `(i, j)`.
+    pub fn sample_point(&self, i: usize, j: usize) -> Pos {
+        Pos::new(
+            self.origin.x + i as f64 * self.cell_size,
+            self.origin.y + j as f64 * self.cell_size,
+        )
+    }
+}
+
+impl Default for ObservationGrid {
+    fn default() -> Self {
+        ObservationGrid {
+            width: STATE_SIZE,
+            height: STATE_SIZE,
+            cell_size: STATE_SCALE as f64,
+            origin: Pos::new(0.0, 0.0),
+        }
+    }
+}
+
+/// What each faction can currently see, recomputed every tick
+/// by the `VisibilitySystem`.
+///
+/// Without fog of war every faction can see everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Visibility {
+    pub fog_of_war: bool,
+    /// The factions whose combined point of view the observation
+    /// shows, i.e. the agent's factions
+    pub observers: Vec<usize>,
+    /// For each faction, which cells of the `ObservationGrid` are in sight,
+    /// indexed by `i * height + j`
+    pub cells: Vec<Vec<bool>>,
+    /// For each faction, the units of other factions in sight
+    pub units: Vec<HashSet<Entity>>,
+}
+
+impl Visibility {
+    /// Whether `faction` can see `unit`, which belongs to `owner`.
+    pub fn can_see_unit(&self, faction: usize, unit: Entity, owner: usize) -> bool {
+        !self.fog_of_war || faction == owner
+            || self.units
+                .get(faction)
+                .map_or(false, |units| units.contains(&unit))
+    }
+
+    /// Whether `faction` can see the cell at index `idx` of the `ObservationGrid`.
+    pub fn can_see_cell(&self, faction: usize, idx: usize) -> bool {
+        !self.fog_of_war
+            || self.cells
+                .get(faction)
+                .and_then(|cells| cells.get(idx))
+                .cloned()
+                .unwrap_or(false)
+    }
+
+    /// Whether any of the observers can see `unit`, which belongs to `owner`.
+    pub fn observes_unit(&self, unit: Entity, owner: usize) -> bool {
+        !self.fog_of_war
+            || self.observers
+                .iter()
+                .any(|faction| self.can_see_unit(*faction, unit, owner))
+    }
+
+    /// Whether any of the observers can see the cell at index `idx`.
+    pub fn observes_cell(&self, idx: usize) -> bool {
+        !self.fog_of_war
+            || self.observers
+                .iter()
+                .any(|faction| self.can_see_cell(*faction, idx))
+    }
+}
+
+/// The `FeatureLayer::LastSeen` value of each cell of the `ObservationGrid`,
+/// indexed by `i * height + j`. This is the fog of war's memory, so it is
+/// kept in the world (and its snapshots) and cleared every episode.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LastSeen(pub Vec<f64>);
+
 /// Any associated data with various game factions.
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Player {
@@ -93,16 +296,55 @@ pub struct Render(pub Viz);
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default, Debug)]
 pub struct NeedsKeyInfo(pub bool);
 
+/// The factions whose units the agent is allowed to order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AgentFactions(pub Vec<usize>);
+
+impl Default for AgentFactions {
+    fn default() -> Self {
+        AgentFactions(vec![0])
+    }
+}
+
+/// The factions controlled by the scenario's `ai_step` Lua function.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScriptedFactions(pub Vec<usize>);
+
 /// The actions coming from the Agent (or replay mechanism)
 #[derive(Clone, PartialEq, Default, Debug)]
 pub struct ActionInput(pub Option<Action>);
 
-#[derive(PartialEq, Default, Clone)]
+#[derive(PartialEq, Default, Clone, Serialize, Deserialize)]
 pub struct Reward(pub HashMap<String, f64>);
 
-#[derive(Eq, PartialEq, Default, Clone, Debug, Hash)]
+#[derive(Eq, PartialEq, Default, Clone, Debug, Hash, Serialize, Deserialize)]
 pub struct Skip(pub bool, pub Option<String>);
 
+/// Non-panicking errors raised by systems during the last update,
+/// these are drained and reported to the core after each step.
+#[derive(PartialEq, Default, Clone, Debug)]
+pub struct Errors(pub Vec<SkyError>);
+
+/// Something that happened to a unit, reported to the scenario's
+/// Lua hooks.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    Spawn(Entity),
+    Damage {
+        victim: Entity,
+        attacker: Entity,
+        amount: f64,
+    },
+    AttackStart { attacker: Entity, target: Entity },
+    /// A unit was given a new order, by the agent or a script
+    OrderIssued(Entity),
+}
+
+/// Events since the Lua hooks last ran, these are drained
+/// by the `LuaSystem` every frame.
+#[derive(PartialEq, Default, Clone, Debug)]
+pub struct GameEvents(pub Vec<GameEvent>);
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct UnitType {
     pub tag: String,
@@ -111,12 +353,22 @@ pub struct UnitType {
     pub shape: Shape,
     pub kill_reward: f64,
     pub death_penalty: f64,
+    /// Given to the agent per point of damage its units of this type
+    /// deal to units it doesn't control, as the `damage_dealt` reward
     pub damage_deal_reward: Option<f64>,
+    /// Given to the agent per point of damage its units of this type
+    /// take, as the `damage_taken` reward
     pub damage_recv_penalty: Option<f64>,
     pub speed: f64,
     pub attack_range: f64,
     pub attack_damage: f64,
     pub attack_delay: f64,
+    pub move_behavior: MoveBehavior,
+    /// Custom numeric attributes, which can be observed
+    /// with `FeatureLayer::Attribute`
+    pub attributes: HashMap<String, f64>,
+    /// How far the unit can see when fog of war is enabled
+    pub sight_range: f64,
 }
 
 impl Default for UnitType {
@@ -134,6 +386,9 @@ impl Default for UnitType {
             attack_range: 10.0,
             attack_delay: 1.0,
             attack_damage: 10.0,
+            move_behavior: MoveBehavior::Straight,
+            attributes: HashMap::new(),
+            sight_range: 100.0,
         }
     }
 }
@@ -173,6 +428,15 @@ impl UnitType {
             }
         }.build();
 
+        if !self.movable {
+            world.write_resource::<NavGrid>().dirty = true;
+        }
+
+        world
+            .write_resource::<GameEvents>()
+            .0
+            .push(GameEvent::Spawn(entity));
+
         let col_storage = world.write::<CollisionHandle>();
 
         let atk_storage = world.write::<AttackSensor>();
@@ -205,29 +469,26 @@ impl UnitType {
         sensor_group.modify_membership(MAX_FACTIONS + faction, true);
         // sensor_group.set_blacklist(&SENSOR_BLACKLIST);
 
-        let (collider, atk_radius) = match self.shape {
-            Shape::Rect { width, height } => {
-                let width = width / COLLISION_SCALE;
-                let height = height / COLLISION_SCALE;
+        let half_extents = collider_half_extents(&self.shape);
 
-                // ncollide likes half widths and heights, so divide by 2
-                let collider = Cuboid::new(Vector2::new(width / 2.0, height / 2.0));
+        let (collider, atk_radius) = match self.shape {
+            Shape::Rect { .. } => {
+                let collider = Cuboid::new(half_extents);
                 let collider = ShapeHandle::new(collider);
 
+                let width = half_extents.x * 2.0;
+                let height = half_extents.y * 2.0;
+
                 let atk_radius = width.max(height) + (self.attack_range / COLLISION_SCALE);
                 let atk_sensor = Ball::new(atk_radius);
                 let atk_sensor = ShapeHandle::new(atk_sensor);
 
                 (collider, atk_sensor)
             }
-            Shape::Triangle { base_len } => {
-                let base_len = base_len / COLLISION_SCALE;
-
-                // equilateral triangle dimensions
-                let half_height = base_len / (2.0 as f64).sqrt() / 2.0;
-                let radius = base_len / 2.0;
+            Shape::Triangle { .. } => {
+                let (radius, half_height) = (half_extents.x, half_extents.y);
 
-                // A cylinder in 2D is an isoscelese triangle in ncollide
+                // Triangles collide as a 2D cylinder, see `collider_half_extents`
                 let collider = Cylinder::new(half_height, radius);
                 let collider = ShapeHandle::new(collider);
 
@@ -0,0 +1,299 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use engine::components::{Pos, Shape};
+use super::ObservationGrid;
+
+/// Width of a navigation grid cell, in world units.
+pub const NAV_CELL_SIZE: f64 = 5.0;
+/// How far paths keep away from static obstacles, roughly
+/// the half-width of a typical unit.
+pub const NAV_CLEARANCE: f64 = 5.0;
+
+/// A grid of cells blocked by `Static` units, used for
+/// pathfinding around obstacles.
+///
+/// The grid is rebuilt lazily whenever it's marked dirty, each rebuild
+/// bumps `generation` so cached paths know to recompute themselves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NavGrid {
+    pub origin: Pos,
+    pub width: usize,
+    pub height: usize,
+    pub cell_size: f64,
+    pub generation: u64,
+    pub dirty: bool,
+    blocked: Vec<bool>,
+}
+
+impl NavGrid {
+    /// Creates a grid covering `world_width x world_height`
+    /// starting at `origin`.
+    pub fn new(origin: Pos, world_width: f64, world_height: f64, cell_size: f64) -> Self {
+        let width = (world_width / cell_size).ceil().max(1.0) as usize;
+        let height = (world_height / cell_size).ceil().max(1.0) as usize;
+
+        NavGrid {
+            origin,
+            width,
+            height,
+            cell_size,
+            generation: 0,
+            dirty: true,
+            blocked: vec![false; width * height],
+        }
+    }
+
+    /// Recomputes the blocked cells from the positions
+    /// and shapes of every static unit.
+    pub fn rebuild<'a, I: IntoIterator<Item = (&'a Pos, &'a Shape)>>(&mut self, statics: I) {
+        for cell in self.blocked.iter_mut() {
+            *cell = false;
+        }
+
+        for (pos, shape) in statics {
+            let (half_w, half_h) = match *shape {
+                Shape::Rect { width, height } => (width / 2.0, height / 2.0),
+                Shape::Triangle { base_len } => (base_len / 2.0, base_len / 2.0),
+            };
+            let (half_w, half_h) = (half_w + NAV_CLEARANCE, half_h + NAV_CLEARANCE);
+
+            let (min_x, min_y) = self.cell(&Pos::new(pos.x - half_w, pos.y - half_h));
+            let (max_x, max_y) = self.cell(&Pos::new(pos.x + half_w, pos.y + half_h));
+
+            for x in min_x..max_x + 1 {
+                for y in min_y..max_y + 1 {
+                    let center = self.center((x, y));
+                    let blocked = match *shape {
+                        Shape::Rect { .. } => {
+                            (center.x - pos.x).abs() <= half_w
+                                && (center.y - pos.y).abs() <= half_h
+                        }
+                        Shape::Triangle { .. } => {
+                            (*center - **pos).norm() <= half_w
+                        }
+                    };
+
+                    if blocked {
+                        let idx = self.index((x, y));
+                        self.blocked[idx] = true;
+                    }
+                }
+            }
+        }
+
+        self.generation += 1;
+        self.dirty = false;
+    }
+
+    /// The cell containing `pos`, positions outside the
+    /// grid are clamped to the nearest edge cell.
+    pub fn cell(&self, pos: &Pos) -> (usize, usize) {
+        let x = ((pos.x - self.origin.x) / self.cell_size).floor().max(0.0) as usize;
+        let y = ((pos.y - self.origin.y) / self.cell_size).floor().max(0.0) as usize;
+
+        (x.min(self.width - 1), y.min(self.height - 1))
+    }
+
+    pub fn is_blocked(&self, cell: (usize, usize)) -> bool {
+        self.blocked[self.index(cell)]
+    }
+
+    fn index(&self, (x, y): (usize, usize)) -> usize {
+        y * self.width + x
+    }
+
+    fn center(&self, (x, y): (usize, usize)) -> Pos {
+        Pos::new(
+            self.origin.x + (x as f64 + 0.5) * self.cell_size,
+            self.origin.y + (y as f64 + 0.5) * self.cell_size,
+        )
+    }
+
+    /// Finds a path from `from` to `to` with A*, returning the waypoints
+    /// in reverse order (so the next one can be popped off the end).
+    ///
+    /// The final waypoint is always `to` itself. If `to` is unreachable
+    /// the path leads to the closest reachable cell instead, so the unit
+    /// gets as close as it can.
+    pub fn find_path(&self, from: &Pos, to: &Pos) -> Vec<Pos> {
+        use std::f64;
+        use std::usize;
+
+        let start = self.cell(from);
+        let goal = self.cell(to);
+        let (start_idx, goal_idx) = (self.index(start), self.index(goal));
+
+        let heuristic = |(x, y): (usize, usize)| {
+            let dx = (x as f64 - goal.0 as f64).abs();
+            let dy = (y as f64 - goal.1 as f64).abs();
+            // Octile distance
+            dx.max(dy) + (f64::consts::SQRT_2 - 1.0) * dx.min(dy)
+        };
+
+        let mut cost = vec![f64::INFINITY; self.blocked.len()];
+        let mut came_from = vec![usize::MAX; self.blocked.len()];
+        let mut closed = vec![false; self.blocked.len()];
+        let mut open = BinaryHeap::new();
+
+        cost[start_idx] = 0.0;
+        open.push(Node {
+            cost: heuristic(start),
+            cell: start,
+        });
+
+        let mut best = (start, heuristic(start));
+
+        while let Some(Node { cell, .. }) = open.pop() {
+            let idx = self.index(cell);
+            if idx == goal_idx {
+                best = (cell, 0.0);
+                break;
+            }
+
+            if closed[idx] {
+                continue;
+            }
+            closed[idx] = true;
+
+            let h = heuristic(cell);
+            if h < best.1 {
+                best = (cell, h);
+            }
+
+            for &(dx, dy) in &NEIGHBORS {
+                let (nx, ny) = (cell.0 as isize + dx, cell.1 as isize + dy);
+                if nx < 0 || ny < 0 || nx >= self.width as isize || ny >= self.height as isize {
+                    continue;
+                }
+                let next = (nx as usize, ny as usize);
+                let next_idx = self.index(next);
+
+                // Units starting inside a blocked area are allowed to step out of it
+                let passable = |c: (usize, usize)| {
+                    let c_idx = self.index(c);
+                    !self.blocked[c_idx] || c_idx == goal_idx || idx == start_idx
+                };
+
+                if !passable(next) {
+                    continue;
+                }
+
+                // No cutting corners diagonally
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && (!passable((next.0, cell.1)) || !passable((cell.0, next.1))) {
+                    continue;
+                }
+
+                let step = if diagonal { f64::consts::SQRT_2 } else { 1.0 };
+                let new_cost = cost[idx] + step;
+
+                if new_cost < cost[next_idx] {
+                    cost[next_idx] = new_cost;
+                    came_from[next_idx] = idx;
+                    open.push(Node {
+                        cost: new_cost + heuristic(next),
+                        cell: next,
+                    });
+                }
+            }
+        }
+
+        let mut path = vec![*to];
+
+        let mut idx = self.index(best.0);
+        // The goal cell's center is replaced by the exact goal
+        if idx == goal_idx {
+            idx = came_from[idx];
+        }
+
+        while idx != usize::MAX && idx != start_idx {
+            let cell = (idx % self.width, idx / self.width);
+            path.push(self.center(cell));
+            idx = came_from[idx];
+        }
+
+        path
+    }
+}
+
+impl NavGrid {
+    /// A grid covering the same region as the observation.
+    pub fn for_world(grid: &ObservationGrid) -> Self {
+        NavGrid::new(
+            grid.origin,
+            grid.world_width(),
+            grid.world_height(),
+            NAV_CELL_SIZE,
+        )
+    }
+}
+
+impl Default for NavGrid {
+    fn default() -> Self {
+        NavGrid::for_world(&ObservationGrid::default())
+    }
+}
+
+const NEIGHBORS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+#[derive(Copy, Clone, PartialEq)]
+struct Node {
+    cost: f64,
+    cell: (usize, usize),
+}
+
+impl Eq for Node {}
+
+// Reversed so the `BinaryHeap` pops the cheapest node first
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .partial_cmp(&self.cost)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NavGrid;
+    use engine::components::{Pos, Shape};
+
+    #[test]
+    fn path_avoids_wall() {
+        let mut grid = NavGrid::new(Pos::new(0.0, 0.0), 100.0, 100.0, 5.0);
+        let wall = (
+            Pos::new(50.0, 40.0),
+            Shape::Rect {
+                width: 10.0,
+                height: 80.0,
+            },
+        );
+        grid.rebuild(vec![(&wall.0, &wall.1)]);
+
+        let from = Pos::new(10.0, 10.0);
+        let to = Pos::new(90.0, 10.0);
+        let path = grid.find_path(&from, &to);
+
+        assert_eq!(path[0], to);
+        assert!(path.iter().all(|pos| !grid.is_blocked(grid.cell(pos))));
+        // Has to go around the bottom of the wall
+        assert!(path.iter().any(|pos| pos.y > 80.0));
+    }
+}
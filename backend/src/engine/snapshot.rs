@@ -0,0 +1,438 @@
+use specs::World;
+use specs::saveload::U64Marker;
+
+use rand::Isaac64Rng;
+
+use engine::components::{AttackData, Color, FactionId, Heading, Hp, MoveData, Pos, Shape, Speed,
+                         UnitTypeTag};
+use engine::resources::{ActionRepeat, Episode, EpisodeClock, EpisodeOutcome, LastSeen, Reward,
+                        SeedRng, Skip, Terminal};
+
+use std::error::Error;
+
+/// Everything needed to restore a single entity. Entity references
+/// (move and attack targets) are stored as markers so they survive
+/// the entity being recreated with a different ID.
+#[derive(Serialize, Deserialize)]
+struct EntityData {
+    marker: U64Marker,
+    pos: Pos,
+    shape: Shape,
+    color: Color,
+    faction: FactionId,
+    tag: UnitTypeTag,
+    hp: Hp,
+    heading: Option<Heading>,
+    speed: Option<Speed>,
+    movable: bool,
+    is_static: bool,
+    move_order: Option<MoveData<U64Marker>>,
+    attack: Option<AttackData<U64Marker>>,
+}
+
+/// A full snapshot of the simulation, the collision world
+/// is not stored and is rebuilt from the entities on load.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    entities: Vec<EntityData>,
+    rng: Isaac64Rng,
+    seed_rng: SeedRng,
+    episode: Episode,
+    terminal: Terminal,
+    outcome: EpisodeOutcome,
+    clock: EpisodeClock,
+    skip: Skip,
+    repeat: ActionRepeat,
+    reward: Reward,
+    last_seen: LastSeen,
+}
+
+/// Serializes the entire world state into `buf`, clearing any existing
+/// contents.
+pub fn serialize(world: &World, mut buf: Vec<u8>) -> Result<Vec<u8>, Box<Error>> {
+    use specs::Join;
+    use specs::saveload::SaveLoadComponent;
+    use engine::components::{Attack, Movable, Move, Static};
+    use bincode::{self, Infinite};
+
+    let entities = world.entities();
+    let markers = world.read::<U64Marker>();
+    let positions = world.read::<Pos>();
+    let shapes = world.read::<Shape>();
+    let colors = world.read::<Color>();
+    let factions = world.read::<FactionId>();
+    let tags = world.read::<UnitTypeTag>();
+    let hps = world.read::<Hp>();
+    let headings = world.read::<Heading>();
+    let speeds = world.read::<Speed>();
+    let movables = world.read::<Movable>();
+    let statics = world.read::<Static>();
+    let moves = world.read::<Move>();
+    let attacks = world.read::<Attack>();
+
+    let mut entity_data = vec![];
+
+    for (id, marker, pos, shape, color, faction, tag, hp) in (
+        &*entities,
+        &markers,
+        &positions,
+        &shapes,
+        &colors,
+        &factions,
+        &tags,
+        &hps,
+    ).join()
+    {
+        let ids = |e| markers.get(e).cloned();
+
+        let move_order = match moves.get(id) {
+            Some(move_order) => Some(SaveLoadComponent::<U64Marker>::save(move_order, ids)?),
+            None => None,
+        };
+
+        let attack = match attacks.get(id) {
+            Some(attack) => Some(SaveLoadComponent::<U64Marker>::save(attack, ids)?),
+            None => None,
+        };
+
+        entity_data.push(EntityData {
+            marker: marker.clone(),
+            pos: *pos,
+            shape: *shape,
+            color: *color,
+            faction: *faction,
+            tag: tag.clone(),
+            hp: *hp,
+            heading: headings.get(id).cloned(),
+            speed: speeds.get(id).cloned(),
+            movable: movables.get(id).is_some(),
+            is_static: statics.get(id).is_some(),
+            move_order,
+            attack,
+        });
+    }
+
+    let snapshot = Snapshot {
+        entities: entity_data,
+        rng: world.read_resource::<Isaac64Rng>().clone(),
+        seed_rng: world.read_resource::<SeedRng>().clone(),
+        episode: *world.read_resource::<Episode>(),
+        terminal: *world.read_resource::<Terminal>(),
+        outcome: *world.read_resource::<EpisodeOutcome>(),
+        clock: *world.read_resource::<EpisodeClock>(),
+        skip: world.read_resource::<Skip>().clone(),
+        repeat: world.read_resource::<ActionRepeat>().clone(),
+        reward: world.read_resource::<Reward>().clone(),
+        last_seen: world.read_resource::<LastSeen>().clone(),
+    };
+
+    buf.clear();
+    bincode::serialize_into(&mut buf, &snapshot, Infinite)?;
+
+    Ok(buf)
+}
+
+/// Replaces the current world state with the one serialized in `buf`.
+///
+/// All existing entities are deleted, and the collision world is rebuilt
+/// from the loaded units' types, so the scenario must already be loaded.
+pub fn deserialize(world: &mut World, buf: &[u8]) -> Result<(), Box<Error>> {
+    use specs::saveload::{Marker, MarkerAllocator, SaveLoadComponent, U64MarkerAllocator};
+    use engine::components::{Attack, Movable, Move, Static};
+    use engine::resources::{NavGrid, SkyCollisionWorld, UnitTypeMap, COLLISION_MARGIN};
+    use std::collections::HashMap;
+    use bincode;
+
+    let snapshot: Snapshot = bincode::deserialize(buf)?;
+
+    world.delete_all();
+    world.maintain();
+
+    *world.write_resource::<SkyCollisionWorld>() = SkyCollisionWorld::new(COLLISION_MARGIN);
+    *world.write_resource::<U64MarkerAllocator>() = U64MarkerAllocator::new();
+    world.write_resource::<NavGrid>().dirty = true;
+
+    // Overwrite in place rather than replacing the resource,
+    // Lua holds a pointer to the RNG
+    *world.write_resource::<Isaac64Rng>() = snapshot.rng;
+    *world.write_resource::<SeedRng>() = snapshot.seed_rng;
+    *world.write_resource::<Episode>() = snapshot.episode;
+    *world.write_resource::<Terminal>() = snapshot.terminal;
+    *world.write_resource::<EpisodeOutcome>() = snapshot.outcome;
+    *world.write_resource::<EpisodeClock>() = snapshot.clock;
+    *world.write_resource::<Skip>() = snapshot.skip;
+    {
+        // The repeat length itself is set by the scenario
+        let repeat = &mut *world.write_resource::<ActionRepeat>();
+        repeat.left = snapshot.repeat.left;
+        repeat.then = snapshot.repeat.then;
+    }
+    *world.write_resource::<Reward>() = snapshot.reward;
+    *world.write_resource::<LastSeen>() = snapshot.last_seen;
+
+    let mut mapping = HashMap::with_capacity(snapshot.entities.len());
+    let mut orders = Vec::with_capacity(snapshot.entities.len());
+
+    for data in snapshot.entities {
+        let entity = {
+            let entity = world
+                .create_entity()
+                .with(data.pos)
+                .with(data.shape)
+                .with(data.color)
+                .with(data.faction)
+                .with(data.tag.clone())
+                .with(data.hp);
+
+            let entity = match data.heading {
+                Some(heading) => entity.with(heading),
+                None => entity,
+            };
+
+            let entity = match data.speed {
+                Some(speed) => entity.with(speed),
+                None => entity,
+            };
+
+            let entity = if data.movable {
+                entity.with(Movable)
+            } else {
+                entity
+            };
+
+            if data.is_static {
+                entity.with(Static)
+            } else {
+                entity
+            }
+        }.build();
+
+        let marker = world
+            .write_resource::<U64MarkerAllocator>()
+            .allocate(entity, Some(data.marker.id()));
+        world.write::<U64Marker>().insert(entity, marker);
+
+        mapping.insert(data.marker.id(), entity);
+        orders.push((entity, data.move_order, data.attack));
+    }
+
+    // Targets can only be resolved once every entity exists
+    {
+        let mut moves = world.write::<Move>();
+        let mut attacks = world.write::<Attack>();
+
+        for (entity, move_order, attack) in orders {
+            let ids = |m: U64Marker| mapping.get(&m.id()).cloned();
+
+            if let Some(move_order) = move_order {
+                moves.insert(entity, Move::load(move_order, ids)?);
+            }
+
+            if let Some(attack) = attack {
+                attacks.insert(entity, Attack::load(attack, ids)?);
+            }
+        }
+    }
+
+    let units: Vec<_> = {
+        use specs::Join;
+
+        let entities = world.entities();
+        let positions = world.read::<Pos>();
+        let factions = world.read::<FactionId>();
+        let tags = world.read::<UnitTypeTag>();
+
+        (&*entities, &positions, &factions, &tags)
+            .join()
+            .map(|(id, pos, faction, tag)| (id, *pos, faction.0, tag.0.clone()))
+            .collect()
+    };
+
+    for (entity, pos, faction, tag) in units {
+        let unit_type = world
+            .read_resource::<UnitTypeMap>()
+            .tag_map
+            .get(&tag)
+            .ok_or_else(|| format!("Unknown unit type in serialized state: {}", tag))?
+            .clone();
+
+        let col_storage = world.write();
+        let atk_storage = world.write();
+        let c_world = &mut *world.write_resource();
+
+        unit_type.register_collision(entity, pos, faction, col_storage, atk_storage, c_world);
+    }
+
+    Ok(())
+}
+
+/// A hash of every unit's position, health, faction, orders and attack
+/// timer along with the RNG and the episode clock, the parts of `serialize`
+/// that change from frame to frame. Units and their targets are identified
+/// by their markers rather than their entity IDs, so the hash survives a
+/// `serialize`/`deserialize` round trip.
+pub fn hash(world: &World) -> u64 {
+    use specs::Join;
+    use specs::saveload::{Marker, SaveLoadComponent};
+    use engine::components::{Attack, Move};
+    use bincode::{self, Infinite};
+    use util;
+
+    let entities = world.entities();
+    let markers = world.read::<U64Marker>();
+    let positions = world.read::<Pos>();
+    let hps = world.read::<Hp>();
+    let factions = world.read::<FactionId>();
+    let moves = world.read::<Move>();
+    let attacks = world.read::<Attack>();
+
+    let mut units: Vec<_> = (&*entities, &markers, &positions, &hps, &factions)
+        .join()
+        .map(|(id, marker, pos, hp, faction)| {
+            // A target without a marker is hashed as no order at all
+            let move_order = moves.get(id).and_then(|move_order| {
+                SaveLoadComponent::<U64Marker>::save(move_order, |e| markers.get(e).cloned()).ok()
+            });
+            let attack = attacks.get(id).and_then(|attack| {
+                SaveLoadComponent::<U64Marker>::save(attack, |e| markers.get(e).cloned()).ok()
+            });
+
+            (
+                marker.id(),
+                pos.x.to_bits(),
+                pos.y.to_bits(),
+                hp.curr_hp.to_bits(),
+                faction.0,
+                move_order,
+                attack,
+            )
+        })
+        .collect();
+    units.sort_by_key(|unit| unit.0);
+
+    let clock = *world.read_resource::<EpisodeClock>();
+    let rng = world.read_resource::<Isaac64Rng>();
+    let seed_rng = world.read_resource::<SeedRng>();
+
+    // Everything here is serializable, as it's all in `Snapshot` too
+    let bytes = bincode::serialize(
+        &(units, &*rng, &*seed_rng, clock.steps, clock.time.to_bits()),
+        Infinite,
+    ).unwrap();
+    util::fnv1a(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hash;
+    use engine::test_util::{inline_rts, move_to, orders, unit};
+
+    #[test]
+    fn snapshots_remember_what_the_fog_hides() {
+        use engine::resources::LastSeen;
+
+        let mut rts = inline_rts(
+            r#"agent_factions = { 1 }, fog_of_war = true, feature_layers = { "last_seen" },"#,
+            r#"
+            { unit_type = "soldier", faction = 1, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "soldier", faction = 0, pos = { x = 150.0, y = 100.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+        let seen = rts.world.read_resource::<LastSeen>().clone();
+        assert!(seen.0.iter().any(|cell| *cell > 0.0));
+
+        let buf = rts.serialize(None).unwrap();
+        rts.restart().unwrap();
+        rts.world.write_resource::<LastSeen>().0.clear();
+        rts.deserialize(&buf).unwrap();
+
+        assert_eq!(*rts.world.read_resource::<LastSeen>(), seen);
+    }
+
+    #[test]
+    fn snapshots_resume_repeated_actions() {
+        use engine::resources::ActionRepeat;
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            "#,
+            "",
+        );
+        rts.action_repeat = Some(4);
+
+        rts.reset().unwrap();
+        rts.action_input(orders(vec![move_to(0, 200.0, 100.0)]));
+        rts.tick();
+
+        let buf = rts.serialize(None).unwrap();
+        let repeat = rts.world.read_resource::<ActionRepeat>().clone();
+        assert_eq!(repeat.left, 3);
+
+        rts.restart().unwrap();
+        rts.deserialize(&buf).unwrap();
+
+        assert_eq!(*rts.world.read_resource::<ActionRepeat>(), repeat);
+        assert!(rts.skip());
+    }
+
+    #[test]
+    fn hash_covers_the_rng_and_orders() {
+        use rand::{Isaac64Rng, Rng};
+        use engine::components::{Attack, Move, MoveBehavior, MoveTarget, Pos};
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 130.0, y = 100.0 } },
+            "#,
+            "",
+        );
+        rts.reset().unwrap();
+
+        let (soldier, tower) = (unit(&rts, 0), unit(&rts, 1));
+        let mut hashes = vec![hash(&rts.world)];
+
+        rts.world.write_resource::<Isaac64Rng>().next_u64();
+        hashes.push(hash(&rts.world));
+
+        rts.world.write::<Move>().insert(
+            soldier,
+            Move {
+                behavior: MoveBehavior::Straight,
+                target: MoveTarget::Ground(Pos::new(200.0, 100.0)),
+            },
+        );
+        hashes.push(hash(&rts.world));
+
+        rts.world.write::<Move>().insert(
+            soldier,
+            Move {
+                behavior: MoveBehavior::Straight,
+                target: MoveTarget::Unit(tower),
+            },
+        );
+        hashes.push(hash(&rts.world));
+
+        rts.world.write::<Attack>().insert(
+            soldier,
+            Attack {
+                target: tower,
+                time_since_last: 0.0,
+            },
+        );
+        hashes.push(hash(&rts.world));
+
+        rts.world.write::<Attack>().get_mut(soldier).unwrap().time_since_last = 0.5;
+        hashes.push(hash(&rts.world));
+
+        for (i, hash) in hashes.iter().enumerate() {
+            assert!(!hashes[i + 1..].contains(hash));
+        }
+    }
+}
@@ -1,6 +1,8 @@
-use specs::{Entities, Fetch, ReadStorage, System, WriteStorage};
-use engine::components::{Attack, Death, Hp, UnitTypeTag};
-use engine::resources::{DeltaT, UnitTypeMap};
+use specs::{Entities, Entity, Fetch, FetchMut, ReadStorage, System, WriteStorage};
+use engine::components::{Attack, Death, FactionId, Hp, UnitTypeTag};
+use engine::resources::{AgentFactions, DeltaT, Errors, GameEvent, GameEvents, Reward, UnitTypeMap};
+
+use error::SkyError;
 
 #[derive(SystemData)]
 pub struct AttackSystemData<'a> {
@@ -11,7 +13,12 @@ pub struct AttackSystemData<'a> {
     delta_t: Fetch<'a, DeltaT>,
     unit_type_map: Fetch<'a, UnitTypeMap>,
     tag: ReadStorage<'a, UnitTypeTag>,
+    faction: ReadStorage<'a, FactionId>,
     entities: Entities<'a>,
+    agent_factions: Fetch<'a, AgentFactions>,
+    errors: FetchMut<'a, Errors>,
+    events: FetchMut<'a, GameEvents>,
+    reward: FetchMut<'a, Reward>,
 }
 
 pub struct AttackSystem;
@@ -23,6 +30,9 @@ impl<'a> System<'a> for AttackSystem {
         use specs::Join;
 
         let delta_t = sys_data.delta_t.0;
+        let agent_factions = &sys_data.agent_factions.0;
+        let faction = &sys_data.faction;
+        let unit_type_map = &sys_data.unit_type_map;
 
         let mut dead_target = vec![];
 
@@ -31,16 +41,85 @@ impl<'a> System<'a> for AttackSystem {
                 dead_target.push(id);
                 continue;
             }
-            let unit_type = sys_data.unit_type_map.tag_map.get(&tag.0).unwrap();
+            let unit_type = match sys_data.unit_type_map.tag_map.get(&tag.0) {
+                Some(unit_type) => unit_type,
+                None => {
+                    sys_data
+                        .errors
+                        .0
+                        .push(SkyError::Config(format!("Unknown unit type {}", tag.0)));
+                    dead_target.push(id);
+                    continue;
+                }
+            };
 
             atk.time_since_last += delta_t;
 
             if atk.time_since_last > unit_type.attack_delay {
-                atk.time_since_last = 0.0;
+                // The first attack is immediate and the cooldown restarts after
+                // every attack, except that ticks longer than the delay can fit
+                // several attacks and carry the time left over to the next
+                let long_tick = delta_t > unit_type.attack_delay && unit_type.attack_delay > 0.0;
+                let attacks = if long_tick && atk.time_since_last.is_finite() {
+                    let attacks = (atk.time_since_last / unit_type.attack_delay).floor();
+                    atk.time_since_last -= attacks * unit_type.attack_delay;
+                    attacks
+                } else {
+                    atk.time_since_last = 0.0;
+                    1.0
+                };
+                let damage = attacks * unit_type.attack_damage;
+
+                let tar_hp = match sys_data.hp.get_mut(atk.target) {
+                    Some(tar_hp) => tar_hp,
+                    // Can't attack something without HP
+                    None => {
+                        dead_target.push(id);
+                        continue;
+                    }
+                };
+
+                // Overkill doesn't count toward rewards
+                let amount = damage.min(tar_hp.curr_hp.max(0.0));
 
-                let tar_hp = sys_data.hp.get_mut(atk.target).unwrap();
+                tar_hp.curr_hp -= damage;
+                sys_data.events.0.push(GameEvent::Damage {
+                    victim: atk.target,
+                    attacker: id,
+                    amount,
+                });
+
+                let is_agent = |unit: Entity| {
+                    faction
+                        .get(unit)
+                        .map_or(false, |owner| agent_factions.contains(&owner.0))
+                };
+                let (agent_attacker, agent_victim) = (is_agent(id), is_agent(atk.target));
+
+                if let Some(deal_reward) = unit_type.damage_deal_reward {
+                    if agent_attacker && !agent_victim {
+                        *sys_data
+                            .reward
+                            .0
+                            .entry("damage_dealt".to_string())
+                            .or_insert(0.0) += amount * deal_reward;
+                    }
+                }
 
-                tar_hp.curr_hp -= unit_type.attack_damage;
+                let recv_penalty = sys_data
+                    .tag
+                    .get(atk.target)
+                    .and_then(|tag| unit_type_map.tag_map.get(&tag.0))
+                    .and_then(|victim_type| victim_type.damage_recv_penalty);
+                if let Some(recv_penalty) = recv_penalty {
+                    if agent_victim {
+                        *sys_data
+                            .reward
+                            .0
+                            .entry("damage_taken".to_string())
+                            .or_insert(0.0) += amount * recv_penalty;
+                    }
+                }
 
                 if tar_hp.curr_hp <= 0.0 {
                     sys_data.death.insert(atk.target, Death { killer: id });
@@ -53,3 +132,102 @@ impl<'a> System<'a> for AttackSystem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use engine::Rts;
+    use engine::test_util::{attack, orders, unit};
+
+    #[test]
+    fn damage_rewards_ignore_overkill() {
+        use engine::resources::RtsState;
+
+        let mut rts = Rts::new();
+        rts.lua_source = Some(
+            r#"
+            function sky_init()
+                return {
+                    unit_types = {
+                        {
+                            tag = "soldier",
+                            attack_range = 50.0,
+                            attack_dmg = 30,
+                            damage_deal_reward = 2,
+                        },
+                        {
+                            tag = "tower",
+                            max_hp = 20,
+                            can_move = false,
+                            shape = { body = "rect", width = 20.0, height = 20.0 },
+                        },
+                    },
+                }
+            end
+
+            function sky_reset(rng)
+                return {
+                    { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+                    { unit_type = "tower", faction = 1, pos = { x = 130.0, y = 100.0 } },
+                }
+            end
+            "#.to_string(),
+        );
+
+        rts.reset().unwrap();
+        rts.step(orders(vec![attack(0, 1)]));
+
+        // Only the 20 HP the tower had count
+        let state = rts.world.read_resource::<RtsState>();
+        assert_eq!(state.0.typed_reward.get("damage_dealt"), Some(&40.0));
+    }
+
+    #[test]
+    fn attack_cooldown_restarts_unless_ticks_are_longer() {
+        use engine::components::Hp;
+
+        // The tower's HP after each tick, with the soldier attacking it
+        let tower_hp = |tick_length: Option<f64>, ticks: usize| {
+            let mut rts = Rts::new();
+            rts.lua_source = Some(
+                r#"
+                function sky_init()
+                    return {
+                        unit_types = {
+                            { tag = "soldier", attack_range = 50.0, attack_delay = 1.5 },
+                            { tag = "tower", max_hp = 1000, can_move = false },
+                        },
+                    }
+                end
+
+                function sky_reset(rng)
+                    return {
+                        { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+                        { unit_type = "tower", faction = 1, pos = { x = 130.0, y = 100.0 } },
+                    }
+                end
+                "#.to_string(),
+            );
+            rts.tick_length = tick_length;
+
+            rts.reset().unwrap();
+            rts.action_input(orders(vec![attack(0, 1)]));
+
+            let mut hp = vec![];
+            for _ in 0..ticks {
+                rts.tick();
+                hp.push(rts.world.read::<Hp>().get(unit(&rts, 1)).unwrap().curr_hp);
+            }
+            hp
+        };
+
+        // At the default 60 fps the cooldown restarts from 0 after every attack
+        let hp = tower_hp(None, 200);
+        let hits: Vec<_> = (0..hp.len())
+            .filter(|&i| i == 0 || hp[i] < hp[i - 1])
+            .collect();
+        assert_eq!(hits, vec![0, 91, 182]);
+
+        // Longer ticks fit several attacks and carry the rest of the cooldown
+        assert_eq!(tower_hp(Some(2.5), 4), vec![990.0, 980.0, 960.0, 940.0]);
+    }
+}
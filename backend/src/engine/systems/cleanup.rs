@@ -1,6 +1,6 @@
 use specs::{Entities, FetchMut, ReadStorage, System, WriteStorage};
-use engine::components::{AttackSensor, CollisionHandle, Death, MovedFlag};
-use engine::resources::SkyCollisionWorld;
+use engine::components::{AttackSensor, CollisionHandle, Death, MovedFlag, Static};
+use engine::resources::{NavGrid, SkyCollisionWorld};
 
 #[derive(SystemData)]
 pub struct CleanupSystemData<'a> {
@@ -8,6 +8,8 @@ pub struct CleanupSystemData<'a> {
     moved: WriteStorage<'a, MovedFlag>,
     entities: Entities<'a>,
     collision_sys: FetchMut<'a, SkyCollisionWorld>,
+    nav: FetchMut<'a, NavGrid>,
+    statics: ReadStorage<'a, Static>,
 
     col_handle: ReadStorage<'a, CollisionHandle>,
     atk_radius: ReadStorage<'a, AttackSensor>,
@@ -31,6 +33,10 @@ impl<'a> System<'a> for CleanupSystem {
         {
             sys_data.entities.delete(id).unwrap();
 
+            if sys_data.statics.get(id).is_some() {
+                sys_data.nav.dirty = true;
+            }
+
             sys_data.collision_sys.remove(&[col_handle.0, atk_radius.0]);
         }
     }
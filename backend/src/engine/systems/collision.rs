@@ -1,31 +1,54 @@
 use specs::{Entity, FetchMut, ReadStorage, System, WriteStorage};
-use engine::resources::SkyCollisionWorld;
-use engine::components::{Attack, AttackSensor, CollisionHandle, FactionId, Move, MovedFlag, Pos};
+use engine::resources::{GameEvent, GameEvents, SkyCollisionWorld};
+use engine::components::{Attack, AttackSensor, CollisionHandle, FactionId, Move, MovedFlag, Pos,
+                         Static};
+
+use nalgebra::{Point2, Vector2};
+use ncollide::query::Contact;
 
 #[derive(SystemData)]
 pub struct CollisionSystemData<'a> {
-    moved: ReadStorage<'a, MovedFlag>,
-    pos: ReadStorage<'a, Pos>,
+    moved: WriteStorage<'a, MovedFlag>,
+    pos: WriteStorage<'a, Pos>,
     c_handle: ReadStorage<'a, CollisionHandle>,
     atk_radius: ReadStorage<'a, AttackSensor>,
     faction: ReadStorage<'a, FactionId>,
+    statics: ReadStorage<'a, Static>,
 
     moving: WriteStorage<'a, Move>,
     attack: WriteStorage<'a, Attack>,
     col_world: FetchMut<'a, SkyCollisionWorld>,
+    events: FetchMut<'a, GameEvents>,
 }
 
-pub struct CollisionSystem;
+#[derive(Default)]
+pub struct CollisionSystem {
+    // Caches to avoid reallocating every frame
+    contact_cache: Vec<Contact<Point2<f64>>>,
+    push_cache: Vec<(Entity, Vector2<f64>)>,
+}
+
+impl CollisionSystem {
+    pub fn new() -> Self {
+        CollisionSystem {
+            contact_cache: Vec::with_capacity(10),
+            push_cache: Vec::with_capacity(100),
+        }
+    }
+}
 
 impl<'a> System<'a> for CollisionSystem {
     type SystemData = CollisionSystemData<'a>;
 
     fn run(&mut self, mut sys_data: Self::SystemData) {
         use specs::Join;
-        use nalgebra::{Isometry2, Vector2};
+        use nalgebra::Isometry2;
         use nalgebra;
         use engine::resources::COLLISION_SCALE;
 
+        let contacts = &mut self.contact_cache;
+        let pushes = &mut self.push_cache;
+
         for (_, pos, c_handle, atk_handle) in (
             &sys_data.moved,
             &sys_data.pos,
@@ -44,7 +67,7 @@ impl<'a> System<'a> for CollisionSystem {
 
         sys_data.col_world.update();
 
-        for (obj1, obj2, _) in sys_data.col_world.contacts() {
+        for (obj1, obj2, alg) in sys_data.col_world.contacts() {
             let eid1 = obj1.data().e;
             let eid2 = obj2.data().e;
 
@@ -67,6 +90,7 @@ impl<'a> System<'a> for CollisionSystem {
                     eid2,
                     &mut sys_data.moving,
                     &mut sys_data.attack,
+                    &mut sys_data.events,
                     faction1,
                     faction2,
                 ),
@@ -75,15 +99,72 @@ impl<'a> System<'a> for CollisionSystem {
                     eid1,
                     &mut sys_data.moving,
                     &mut sys_data.attack,
+                    &mut sys_data.events,
                     faction2,
                     faction1,
                 ),
                 (true, true) => {} // technically unreachable with our blacklist
                 (false, false) => {
-                    continue; //unimplemented, actual bodies are colliding
+                    // Static units never move, so the other
+                    // unit has to be pushed the whole way out
+                    let (share1, share2) = match (
+                        sys_data.statics.get(eid1).is_some(),
+                        sys_data.statics.get(eid2).is_some(),
+                    ) {
+                        (true, true) => continue,
+                        (true, false) => (0.0, 1.0),
+                        (false, true) => (1.0, 0.0),
+                        (false, false) => (0.5, 0.5),
+                    };
+
+                    contacts.clear();
+                    alg.contacts(contacts);
+
+                    let deepest = contacts
+                        .iter()
+                        .filter(|c| c.depth > 0.0)
+                        .max_by(|c1, c2| c1.depth.partial_cmp(&c2.depth).unwrap());
+
+                    if let Some(contact) = deepest {
+                        // The normal points from the first object toward the second
+                        let push = contact.normal * contact.depth * COLLISION_SCALE;
+
+                        if share1 > 0.0 {
+                            pushes.push((eid1, -push * share1));
+                        }
+
+                        if share2 > 0.0 {
+                            pushes.push((eid2, push * share2));
+                        }
+                    }
                 }
             }
         }
+
+        for (id, push) in pushes.drain(..) {
+            let pos = match sys_data.pos.get_mut(id) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            **pos += push;
+
+            // The colliders must be moved now, the unit
+            // won't necessarily be flagged as moving next frame
+            let iso = Isometry2::new(
+                Vector2::new(pos.x / COLLISION_SCALE, pos.y / COLLISION_SCALE),
+                nalgebra::zero(),
+            );
+
+            if let Some(c_handle) = sys_data.c_handle.get(id) {
+                sys_data.col_world.set_position(c_handle.0, iso);
+            }
+
+            if let Some(atk_handle) = sys_data.atk_radius.get(id) {
+                sys_data.col_world.set_position(atk_handle.0, iso);
+            }
+
+            sys_data.moved.insert(id, MovedFlag);
+        }
     }
 }
 
@@ -92,6 +173,7 @@ fn acquire_target<'a>(
     other_id: Entity,
     moving: &mut WriteStorage<'a, Move>,
     attack: &mut WriteStorage<'a, Attack>,
+    events: &mut GameEvents,
     faction1: usize,
     faction2: usize,
 ) {
@@ -127,5 +209,47 @@ fn acquire_target<'a>(
                 time_since_last: f64::INFINITY,
             },
         );
+        events.0.push(GameEvent::AttackStart {
+            attacker: me,
+            target: other_id,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use engine::test_util::{inline_rts, unit};
+    use scaii_defs::protos::Action;
+
+    #[test]
+    fn only_dynamic_units_are_pushed_apart() {
+        use engine::components::Pos;
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "soldier", faction = 1, pos = { x = 104.0, y = 100.0 } },
+            { unit_type = "soldier", faction = 0, pos = { x = 300.0, y = 300.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 310.0, y = 300.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+        for _ in 0..5 {
+            rts.step(Action::default());
+        }
+
+        let pos = |id| *rts.world.read::<Pos>().get(unit(&rts, id)).unwrap();
+
+        // Overlapping dynamic units both give way
+        let (left, right) = (pos(0), pos(1));
+        assert!(left.x < 100.0);
+        assert!(right.x > 104.0);
+
+        // Static units stay put and the other unit is pushed the whole way
+        assert_eq!(pos(3), Pos::new(310.0, 300.0));
+        assert!(pos(2).x < 300.0);
     }
 }
@@ -1,18 +1,24 @@
-use specs::{Entities, FetchMut, Index, ReadStorage, System, WriteStorage};
-use engine::components::{Movable, Move};
-use engine::resources::Skip;
+use specs::{Entities, Entity, Fetch, FetchMut, Index, ReadStorage, System, WriteStorage};
+use engine::components::{Attack, FactionId, Movable, Move, Pos, UnitTypeTag};
+use engine::resources::{ActionRepeat, AgentFactions, Errors, GameEvent, GameEvents, Skip,
+                        UnitTypeMap};
 use engine::ActionInput;
 
+use error::SkyError;
+
 use scaii_defs::protos::Action as ScaiiAction;
+use protos::UnitAction;
 
 #[derive(SystemData)]
 pub struct InputSystemData<'a> {
-    movable: ReadStorage<'a, Movable>,
+    agent_factions: Fetch<'a, AgentFactions>,
     input: FetchMut<'a, ActionInput>,
-    ids: Entities<'a>,
 
+    repeat: FetchMut<'a, ActionRepeat>,
     skip: FetchMut<'a, Skip>,
-    moves: WriteStorage<'a, Move>,
+    errors: FetchMut<'a, Errors>,
+    events: FetchMut<'a, GameEvents>,
+    orders: OrderData<'a>,
 }
 
 #[derive(Default)]
@@ -29,83 +35,349 @@ impl<'a> System<'a> for InputSystem {
 
     fn run(&mut self, mut sys_data: Self::SystemData) {
         use std::mem;
-        use engine::components::{MoveBehavior, MoveTarget};
-
-        let actions = mem::replace(&mut sys_data.input.0, None);
 
-        let (actions, skip, skip_lua) = to_action_list(actions.unwrap_or(Default::default()));
+        let skipping_lua = sys_data.skip.0 && sys_data.skip.1.is_some();
 
-        *sys_data.skip = Skip(skip, skip_lua);
-
-        for action in actions {
-            let entity = sys_data.ids.entity(action.unit_id);
-
-            // Maybe set an error state later?
-            if !sys_data.ids.is_alive(entity) {
-                continue;
+        let actions = match mem::replace(&mut sys_data.input.0, None) {
+            // Empty actions leave a running skip predicate going
+            Some(ref actions) if actions.alternate_actions.is_none() && skipping_lua => return,
+            Some(actions) => actions,
+            // Between actions, the last one is repeated and then
+            // a skip predicate is left running until it says to stop
+            None => {
+                if sys_data.repeat.left > 0 {
+                    sys_data.repeat.left -= 1;
+                    if sys_data.repeat.left == 0 {
+                        *sys_data.skip = sys_data.repeat.then.clone();
+                    }
+                } else if !skipping_lua {
+                    *sys_data.skip = Skip(false, None);
+                }
+                return;
             }
+        };
 
-            if !sys_data.movable.get(entity).is_some() {
-                continue;
+        let (actions, skip, skip_lua) = match to_action_list(
+            actions,
+            &sys_data.agent_factions.0,
+            &mut sys_data.errors.0,
+        ) {
+            Ok(actions) => actions,
+            Err(e) => {
+                sys_data.errors.0.push(e);
+                Default::default()
             }
+        };
 
-            let move_order = match action.action {
-                ActionTarget::Attack(tar_id) => {
-                    let target = sys_data.ids.entity(tar_id);
-                    if !sys_data.ids.is_alive(target) {
-                        continue;
-                    }
-
-                    Move {
-                        behavior: MoveBehavior::Straight,
-                        target: MoveTarget::Unit(target),
-                    }
-                }
-            };
-
-            sys_data.moves.insert(entity, move_order);
+        // Every tick but the last is skipped, so the repeated
+        // ticks' rewards are added up in the output
+        sys_data.repeat.left = sys_data.repeat.ticks - 1;
+        if sys_data.repeat.left > 0 {
+            sys_data.repeat.then = Skip(skip, skip_lua);
+            *sys_data.skip = Skip(true, None);
+        } else {
+            *sys_data.skip = Skip(skip, skip_lua);
         }
 
-        // for (pos, moves, id) in (&mut sys_data.positions, &sys_data.moves, &*sys_data.ids).join() {}
+        for order in actions {
+            match sys_data.orders.issue(&order) {
+                Ok(Some(unit)) => sys_data.events.0.push(GameEvent::OrderIssued(unit)),
+                Ok(None) => {}
+                Err(e) => sys_data.errors.0.push(e),
+            }
+        }
     }
 }
 
+/// An order for a single unit, from the agent or a Lua script.
 #[derive(Debug, Copy, Clone, PartialEq)]
-struct Action {
-    unit_id: Index,
-    action: ActionTarget,
+pub struct Order {
+    pub unit_id: Index,
+    pub target: OrderTarget,
+    /// The faction this order was issued for, if any. Otherwise
+    /// the order is from the agent and may be for any faction it controls.
+    pub faction: Option<usize>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
-enum ActionTarget {
+pub enum OrderTarget {
     Attack(Index),
+    Move(Pos),
 }
 
-fn to_action_list(raw: ScaiiAction) -> (Vec<Action>, bool, Option<String>) {
+/// Everything needed to carry out unit orders, shared between
+/// the `InputSystem` and orders issued from Lua.
+#[derive(SystemData)]
+pub struct OrderData<'a> {
+    movable: ReadStorage<'a, Movable>,
+    tag: ReadStorage<'a, UnitTypeTag>,
+    faction: ReadStorage<'a, FactionId>,
+    unit_types: Fetch<'a, UnitTypeMap>,
+    agent_factions: Fetch<'a, AgentFactions>,
+    ids: Entities<'a>,
+
+    moves: WriteStorage<'a, Move>,
+    attacks: WriteStorage<'a, Attack>,
+}
+
+impl<'a> OrderData<'a> {
+    /// The unit's current move order, if any.
+    pub fn move_order(&self, entity: Entity) -> Option<&Move> {
+        self.moves.get(entity)
+    }
+
+    /// The unit's current attack, if it's engaging something.
+    pub fn attack(&self, entity: Entity) -> Option<&Attack> {
+        self.attacks.get(entity)
+    }
+
+    /// Gives the unit its order, replacing its current one, and returns
+    /// the unit if it was given the order.
+    ///
+    /// Orders for units that can't move are ignored.
+    pub fn issue(&mut self, order: &Order) -> Result<Option<Entity>, SkyError> {
+        use engine::components::{MoveBehavior, MoveTarget};
+
+        let entity = self.ids.entity(order.unit_id);
+
+        if !self.ids.is_alive(entity) {
+            return Err(SkyError::InvalidOrder(format!(
+                "Unit {} does not exist",
+                order.unit_id
+            )));
+        }
+
+        // Every unit is built with a faction
+        let owner = self.faction.get(entity).unwrap().0;
+        match order.faction {
+            Some(faction) if faction != owner => {
+                return Err(SkyError::InvalidOrder(format!(
+                    "Unit {} belongs to faction {}, but was ordered by faction {}",
+                    order.unit_id, owner, faction
+                )))
+            }
+            None if !self.agent_factions.0.contains(&owner) => {
+                return Err(SkyError::InvalidOrder(format!(
+                    "Unit {} belongs to faction {}, which the agent doesn't control",
+                    order.unit_id, owner
+                )))
+            }
+            _ => {}
+        }
+
+        if !self.movable.get(entity).is_some() {
+            return Ok(None);
+        }
+
+        let behavior = self.tag
+            .get(entity)
+            .and_then(|tag| self.unit_types.tag_map.get(&tag.0))
+            .map(|unit_type| unit_type.move_behavior)
+            .unwrap_or(MoveBehavior::Straight);
+
+        let move_order = match order.target {
+            OrderTarget::Attack(tar_id) => {
+                let target = self.ids.entity(tar_id);
+                if !self.ids.is_alive(target) {
+                    return Err(SkyError::InvalidOrder(format!(
+                        "Unit {} cannot attack unit {}, it does not exist",
+                        order.unit_id, tar_id
+                    )));
+                }
+
+                Move {
+                    behavior,
+                    target: MoveTarget::Unit(target),
+                }
+            }
+            OrderTarget::Move(pos) => {
+                // Moving units give up their current target
+                self.attacks.remove(entity);
+
+                Move {
+                    behavior,
+                    target: MoveTarget::Ground(pos),
+                }
+            }
+        };
+
+        self.moves.insert(entity, move_order);
+
+        Ok(Some(entity))
+    }
+}
+
+/// Decodes the RTS-specific action list, individual malformed actions
+/// and lists for factions the agent doesn't control are dropped
+/// and reported in `errors`.
+fn to_action_list(
+    raw: ScaiiAction,
+    agent_factions: &[usize],
+    errors: &mut Vec<SkyError>,
+) -> Result<(Vec<Order>, bool, Option<String>), SkyError> {
     use prost::Message;
-    use protos::{ActionList, AttackUnit};
-    use protos::unit_action::Action as RtsAction;
+    use protos::{ActionList, FactionActions};
 
     if raw.alternate_actions.is_none() {
-        return Default::default();
+        return Ok(Default::default());
+    }
+
+    let action: ActionList = ActionList::decode(raw.alternate_actions.unwrap())?;
+
+    let mut actions = to_actions(action.actions, None, errors);
+
+    for FactionActions {
+        faction,
+        actions: faction_actions,
+    } in action.faction_actions
+    {
+        let faction = faction as usize;
+
+        if !agent_factions.contains(&faction) {
+            errors.push(SkyError::InvalidOrder(format!(
+                "Actions given for faction {}, which the agent doesn't control",
+                faction
+            )));
+            continue;
+        }
+
+        let faction_actions = to_actions(faction_actions, Some(faction), errors);
+        actions.extend(faction_actions);
     }
 
-    let action: ActionList =
-        ActionList::decode(raw.alternate_actions.unwrap()).expect("Could parse inner message");
-
-    let actions = action
-        .actions
-        .into_iter()
-        .map(|a| Action {
-            unit_id: a.unit_id as Index,
-            action: match a.action.expect("Expected an action descriptor") {
-                RtsAction::AttackUnit(AttackUnit { target_id }) => {
-                    ActionTarget::Attack(target_id as Index)
+    Ok((actions, action.skip.unwrap_or_default(), action.skip_lua))
+}
+
+fn to_actions(
+    raw: Vec<UnitAction>,
+    faction: Option<usize>,
+    errors: &mut Vec<SkyError>,
+) -> Vec<Order> {
+    use protos::{AttackUnit, MoveTo};
+    use protos::unit_action::Action as RtsAction;
+
+    raw.into_iter()
+        .filter_map(|a| {
+            let target = match a.action {
+                Some(RtsAction::AttackUnit(AttackUnit { target_id })) => {
+                    OrderTarget::Attack(target_id as Index)
+                }
+                Some(RtsAction::MoveTo(MoveTo { pos })) => {
+                    OrderTarget::Move(Pos::new(pos.x, pos.y))
+                }
+                None => {
+                    errors.push(SkyError::InvalidOrder(format!(
+                        "No action given for unit {}",
+                        a.unit_id
+                    )));
+                    return None;
                 }
-                _ => unimplemented!(),
-            },
+            };
+
+            Some(Order {
+                unit_id: a.unit_id as Index,
+                target,
+                faction,
+            })
         })
-        .collect();
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use engine::test_util::{action, attack, inline_rts, move_to, orders, unit};
+    use scaii_defs::protos::Action;
+
+    #[test]
+    fn move_order_cancels_attack() {
+        use engine::components::{Attack, Pos};
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 130.0, y = 100.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+        rts.step(orders(vec![attack(0, 1)]));
+        rts.step(Action::default());
+
+        let (soldier, tower) = (unit(&rts, 0), unit(&rts, 1));
+        assert_eq!(
+            rts.world.read::<Attack>().get(soldier).map(|atk| atk.target),
+            Some(tower)
+        );
+
+        rts.step(orders(vec![move_to(0, 300.0, 100.0)]));
+        assert!(rts.world.read::<Attack>().get(soldier).is_none());
+
+        // The target is still in range, but it isn't picked back up
+        for _ in 0..5 {
+            rts.step(Action::default());
+        }
+        assert!(rts.world.read::<Attack>().get(soldier).is_none());
+        assert!(rts.world.read::<Pos>().get(soldier).unwrap().x > 100.0);
+    }
+
+    #[test]
+    fn orders_for_uncontrolled_units_are_rejected() {
+        use engine::components::Move;
+        use engine::resources::Errors;
+        use error::SkyError;
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "soldier", faction = 1, pos = { x = 400.0, y = 400.0 } },
+            "#,
+            "",
+        );
 
-    (actions, action.skip.unwrap_or_default(), action.skip_lua)
+        rts.reset().unwrap();
+        rts.action_input(orders(vec![
+            move_to(0, 200.0, 100.0),
+            move_to(1, 300.0, 400.0),
+        ]));
+        rts.tick();
+
+        let errors = rts.world.read_resource::<Errors>();
+        assert!(errors.0.iter().any(|e| match *e {
+            SkyError::InvalidOrder(_) => true,
+            _ => false,
+        }));
+
+        let moves = rts.world.read::<Move>();
+        assert!(moves.get(unit(&rts, 0)).is_some());
+        assert!(moves.get(unit(&rts, 1)).is_none());
+    }
+
+    #[test]
+    fn empty_actions_keep_skipping() {
+        use protos::ActionList;
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+        rts.action_input(action(ActionList {
+            skip: Some(true),
+            skip_lua: Some("true".to_string()),
+            ..Default::default()
+        }));
+        rts.tick();
+        assert!(rts.skip());
+
+        rts.action_input(Action::default());
+        rts.tick();
+        assert!(rts.skip());
+    }
 }
@@ -1,32 +1,66 @@
-use rlua::{Lua, Table};
-use scaii_defs::protos::Error as ScaiiError;
+use rlua::{Lua, Table, ToLuaMulti, Value};
 
-use specs::{Fetch, FetchMut, ReadStorage, System, World};
+use specs::{Entities, Entity, Fetch, FetchMut, Index, ReadStorage, System, World};
 
-use std::error::Error;
 use std::path::Path;
 use std::fmt::Debug;
 
-use engine::components::{Death, FactionId, UnitTypeTag};
-use engine::resources::{Reward, Skip, Terminal, UnitTypeMap};
+use engine::components::{Death, FactionId, Hp, Pos, UnitTypeTag};
+use engine::resources::{AgentFactions, DeltaT, EpisodeClock, EpisodeLimits, EpisodeOutcome,
+                        Errors, GameEvents, OutcomeRewards, Reward, ScriptedFactions, Skip,
+                        SkyCollisionWorld, Terminal, UnitTypeMap};
+use engine::systems::input::OrderData;
+
+use error::SkyError;
+
+use self::userdata::{UserDataUnit, WorldChange};
 
 pub(crate) mod userdata;
 
+/// Every hook `LuaSystem::run_hooks` may call.
+const HOOKS: &[&str] = &[
+    "on_tick",
+    "on_spawn",
+    "on_order_issued",
+    "on_attack_start",
+    "on_damage",
+    "on_death",
+    "ai_step",
+    "on_timeout",
+    "on_episode_end",
+];
+
 #[derive(SystemData)]
 pub struct LuaSystemData<'a> {
     death: ReadStorage<'a, Death>,
     faction: ReadStorage<'a, FactionId>,
     tag: ReadStorage<'a, UnitTypeTag>,
+    pos: ReadStorage<'a, Pos>,
+    hp: ReadStorage<'a, Hp>,
+    ids: Entities<'a>,
 
     unit_type: Fetch<'a, UnitTypeMap>,
+    col_world: Fetch<'a, SkyCollisionWorld>,
+    scripted: Fetch<'a, ScriptedFactions>,
+    delta_t: Fetch<'a, DeltaT>,
+    agent_factions: Fetch<'a, AgentFactions>,
+    outcome_rewards: Fetch<'a, OutcomeRewards>,
+    limits: Fetch<'a, EpisodeLimits>,
+    orders: OrderData<'a>,
 
     skip: FetchMut<'a, Skip>,
     reward: FetchMut<'a, Reward>,
     terminal: FetchMut<'a, Terminal>,
+    outcome: FetchMut<'a, EpisodeOutcome>,
+    clock: FetchMut<'a, EpisodeClock>,
+    errors: FetchMut<'a, Errors>,
+    events: FetchMut<'a, GameEvents>,
 }
 
 pub struct LuaSystem {
     lua: Lua,
+    /// Spawns and removals from the last run, see `apply_changes`
+    changes: Vec<WorldChange>,
 }
 
 unsafe impl Send for LuaSystem {}
@@ -35,83 +69,331 @@ impl<'a> System<'a> for LuaSystem {
     type SystemData = LuaSystemData<'a>;
 
     fn run(&mut self, mut sys_data: Self::SystemData) {
+        if let Err(e) = self.run_hooks(&mut sys_data) {
+            sys_data.errors.0.push(e);
+        }
+    }
+}
+
+impl LuaSystem {
+    pub fn new() -> Self {
+        LuaSystem {
+            lua: Lua::new(),
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn from_lua(lua: Lua) -> Self {
+        LuaSystem {
+            lua: lua,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Runs the scenario's per-frame Lua callbacks, stopping at the
+    /// first one that fails.
+    ///
+    /// Each hook is optional, and they're called in this order:
+    ///
+    /// * `on_tick(world, dt)`
+    /// * `on_spawn(world, unit)`, `on_order_issued(world, unit)`,
+    ///   `on_attack_start(world, attacker, target)` and
+    ///   `on_damage(world, victim, attacker, amount)`, in the order they happened
+    /// * `on_death(world, unit, killer)`
+    /// * `ai_step(world, faction)`, for each scripted faction
+    /// * `on_timeout(world)`, if the episode hit its time limit without
+    ///   ending, which may call `world:victory` (otherwise it's a draw)
+    /// * `on_episode_end(world, winner)`, once the episode is over,
+    ///   the winner is `nil` for a draw
+    ///
+    /// The world is only set up when the scenario defines a hook or
+    /// a `skip_lua` predicate is running.
+    fn run_hooks(&mut self, sys_data: &mut LuaSystemData) -> Result<(), SkyError> {
         use specs::Join;
-        use self::userdata::{UserDataReadWorld, UserDataUnit, UserDataWorld};
+        use engine::resources::{GameEvent, Outcome};
+        use self::userdata::{UserDataReadWorld, UserDataWorld, WorldView};
+        use std::mem;
+        use std::rc::Rc;
 
-        sys_data.reward.0.clear();
+        let events = mem::replace(&mut sys_data.events.0, Vec::new());
 
-        let world = UserDataWorld { victory: None };
-        self.lua.globals().set("__sky_world", world).unwrap();
+        sys_data.clock.time += sys_data.delta_t.0;
+        sys_data.clock.steps += 1;
 
-        for (faction, tag, death) in (&sys_data.faction, &sys_data.tag, &sys_data.death).join() {
-            let killer_faction = sys_data.faction.get(death.killer).unwrap();
-            let friendly_kill = faction == killer_faction;
+        reward_deaths(sys_data)?;
 
-            let unit1 = UserDataUnit { faction: *faction };
-            let unit2 = UserDataUnit {
-                faction: *killer_faction,
+        // Building the world for Lua copies every unit, so it's only
+        // done if there's a hook or skip predicate to look at it
+        let skipping_lua = sys_data.skip.0 && sys_data.skip.1.is_some();
+        let view = if skipping_lua || self.has_hooks() {
+            let units = (&*sys_data.ids)
+                .join()
+                .filter(|id| sys_data.death.get(*id).is_none())
+                .filter_map(|id| unit_handle(sys_data, id))
+                .collect();
+
+            let view = Rc::new(WorldView::new(units, &sys_data.col_world));
+            let world = UserDataWorld {
+                view: Rc::clone(&view),
+                ..UserDataWorld::default()
             };
+            self.lua
+                .globals()
+                .set("__sky_world", world)
+                .map_err(|e| SkyError::lua("setting up the world", e, false))?;
 
-            self.lua.globals().set("__sky_u1", unit1).unwrap();
+            Some(view)
+        } else {
+            None
+        };
 
-            self.lua.globals().set("__sky_u2", unit2).unwrap();
+        if view.is_some() {
+            {
+                let lua = &self.lua;
+                let world: Value = lua.globals()
+                    .get("__sky_world")
+                    .map_err(|e| SkyError::lua("setting up the world", e, false))?;
 
-            self.lua
-                .exec::<()>(
-                    "on_death(__sky_world, __sky_u1, __sky_u2)",
-                    Some("calling on_death"),
-                )
-                .unwrap();
+                call_hook(lua, "on_tick", (world.clone(), sys_data.delta_t.0))?;
+
+                for event in events {
+                    match event {
+                        GameEvent::Spawn(unit) => {
+                            if let Some(unit) = unit_handle(sys_data, unit) {
+                                call_hook(lua, "on_spawn", (world.clone(), unit))?;
+                            }
+                        }
+                        GameEvent::Damage {
+                            victim,
+                            attacker,
+                            amount,
+                        } => {
+                            let handles = (
+                                unit_handle(sys_data, victim),
+                                unit_handle(sys_data, attacker),
+                            );
+                            if let (Some(victim), Some(attacker)) = handles {
+                                call_hook(
+                                    lua,
+                                    "on_damage",
+                                    (world.clone(), victim, attacker, amount),
+                                )?;
+                            }
+                        }
+                        GameEvent::AttackStart { attacker, target } => {
+                            let handles = (
+                                unit_handle(sys_data, attacker),
+                                unit_handle(sys_data, target),
+                            );
+                            if let (Some(attacker), Some(target)) = handles {
+                                call_hook(
+                                    lua,
+                                    "on_attack_start",
+                                    (world.clone(), attacker, target),
+                                )?;
+                            }
+                        }
+                        GameEvent::OrderIssued(unit) => {
+                            if let Some(unit) = unit_handle(sys_data, unit) {
+                                call_hook(lua, "on_order_issued", (world.clone(), unit))?;
+                            }
+                        }
+                    }
+                }
+
+                for (death, id) in (&sys_data.death, &*sys_data.ids).join() {
+                    // The killer may have died in the same frame
+                    let handles = (unit_handle(sys_data, id), unit_handle(sys_data, death.killer));
+                    if let (Some(unit1), Some(unit2)) = handles {
+                        call_hook(lua, "on_death", (world.clone(), unit1, unit2))?;
+                    }
+                }
+            }
 
-            let u_type = sys_data.unit_type.tag_map.get(&tag.0).unwrap();
+            self.run_ai(&sys_data.scripted.0)?;
+        }
 
-            if killer_faction.0 != 0 || friendly_kill {
-                *sys_data.reward.0.entry("death".to_string()).or_insert(0.0) +=
-                    u_type.death_penalty;
-            } else {
-                *sys_data.reward.0.entry("kill".to_string()).or_insert(0.0) += u_type.kill_reward;
+        let mut world: UserDataWorld = match view {
+            Some(_) => self.lua
+                .globals()
+                .get("__sky_world")
+                .map_err(|e| SkyError::lua("reading the world", e, false))?,
+            None => UserDataWorld::default(),
+        };
+
+        // `on_timeout` may pick the outcome, otherwise it's a draw
+        if world.outcome.is_none() && sys_data.limits.reached(&sys_data.clock) {
+            if view.is_some() {
+                {
+                    let lua = &self.lua;
+                    let world: Value = lua.globals()
+                        .get("__sky_world")
+                        .map_err(|e| SkyError::lua("reading the world", e, false))?;
+
+                    call_hook(lua, "on_timeout", world)?;
+                }
+
+                world = self.lua
+                    .globals()
+                    .get("__sky_world")
+                    .map_err(|e| SkyError::lua("reading the world", e, false))?;
             }
+            world.outcome = Some(world.outcome.unwrap_or(Outcome::Draw));
+            sys_data.outcome.truncated = true;
         }
 
-        let world: UserDataWorld = self.lua.globals().get("__sky_world").unwrap();
-        if world.victory.is_some() {
+        if let Some(outcome) = world.outcome {
             sys_data.terminal.0 = true;
+            sys_data.outcome.outcome = Some(outcome);
+
+            if let Outcome::Victory(winner) = outcome {
+                let rewards = &sys_data.outcome_rewards;
+                let (kind, reward) = if sys_data.agent_factions.0.contains(&winner) {
+                    ("victory", rewards.victory)
+                } else {
+                    ("failure", rewards.failure)
+                };
+
+                *sys_data.reward.0.entry(kind.to_string()).or_insert(0.0) += reward;
+            }
+        }
+
+        for order in &world.orders {
+            match sys_data.orders.issue(order) {
+                // Reported to `on_order_issued` next frame
+                Ok(Some(unit)) => sys_data.events.0.push(GameEvent::OrderIssued(unit)),
+                Ok(None) => {}
+                Err(e) => sys_data.errors.0.push(e),
+            }
+        }
+        self.changes.extend(world.changes.iter().cloned());
+
+        if sys_data.terminal.0 && view.is_some() {
+            let lua = &self.lua;
+            let world: Value = lua.globals()
+                .get("__sky_world")
+                .map_err(|e| SkyError::lua("reading the world", e, false))?;
+
+            let winner = match sys_data.outcome.outcome {
+                Some(Outcome::Victory(winner)) => Some(winner),
+                _ => None,
+            };
+
+            call_hook(lua, "on_episode_end", (world, winner))?;
         }
 
         if sys_data.skip.0 {
-            if sys_data.terminal.0 {
+            let keep_skipping = if sys_data.terminal.0 {
+                false
+            } else if let (Some(src), Some(view)) = (sys_data.skip.1.clone(), view) {
+                self.skip_predicate(&src, UserDataReadWorld { view })?
+            } else {
+                true
+            };
+
+            if !keep_skipping {
                 *sys_data.skip = Skip(false, None);
-            } else if let Some(ref src) = sys_data.skip.1 {
-                self.lua
-                    .globals()
-                    .set("__sky_read_world", UserDataReadWorld)
-                    .unwrap();
-                self.lua
-                    .exec::<()>(src, Some("Skip lua"))
-                    .expect("Could not execute lua to skip current state");
             }
         }
+
+        Ok(())
     }
-}
-impl LuaSystem {
-    pub fn new() -> Self {
-        LuaSystem { lua: Lua::new() }
+
+    /// Whether the scenario defines any of the hooks `run_hooks` calls.
+    fn has_hooks(&self) -> bool {
+        let globals = self.lua.globals();
+        HOOKS.iter().any(|name| match globals.get::<_, Value>(*name) {
+            Ok(Value::Function(_)) => true,
+            _ => false,
+        })
     }
 
-    pub fn from_lua(lua: Lua) -> Self {
-        LuaSystem { lua: lua }
+    /// Spawns and removes the units the scenario asked for during the
+    /// last run, which needs the whole world rather than system data.
+    ///
+    /// This must be called between running the `LuaSystem` and the
+    /// output systems, so new units are sent in full and removed ones
+    /// are deleted in the same frame.
+    pub fn apply_changes(&mut self, world: &mut World) {
+        use engine::resources::{Errors, NeedsKeyInfo};
+
+        let mut spawned = false;
+        for change in self.changes.drain(..) {
+            let result = match change {
+                WorldChange::Spawn {
+                    unit_type,
+                    faction,
+                    pos,
+                } => spawn_unit(world, &unit_type, faction, pos).map(|_| spawned = true),
+                WorldChange::Remove(id) => remove_unit(world, id),
+            };
+
+            if let Err(e) = result {
+                world.write_resource::<Errors>().0.push(e);
+            }
+        }
+
+        // The render and state deltas only describe units that already exist
+        if spawned {
+            world.write_resource::<NeedsKeyInfo>().0 = true;
+        }
     }
 
-    pub fn add_lua(&mut self, src: &str) -> Result<(), ScaiiError> {
+    /// Evaluates a `skip_lua` predicate, which may be an expression or
+    /// a chunk that returns a boolean, with the world bound to `world`.
+    fn skip_predicate(&mut self, src: &str, world: UserDataReadWorld) -> Result<bool, SkyError> {
+        use rlua::Function;
+
+        let name = Some("Skip lua");
         self.lua
-            .exec::<()>(src, Some("Loading Scenario Script File"))
-            .or_else(|e| {
-                Err(ScaiiError {
-                    fatal: Some(true),
-                    error_info: None,
-                    description: format!("Cannot execute scenario description lua file:\n\t{}", e),
-                })
+            .eval::<Function>(&format!("function(world) return {} end", src), name)
+            .or_else(|_| {
+                self.lua
+                    .eval::<Function>(&format!("function(world) {} end", src), name)
             })
+            .and_then(|predicate| predicate.call(world))
+            .map_err(|e| SkyError::lua("executing skip Lua", e, false))
+    }
+
+    /// Calls `ai_step(world, faction)` for each scripted faction, if the
+    /// scenario defines it. Orders it gives are left in the world.
+    fn run_ai(&mut self, scripted: &[usize]) -> Result<(), SkyError> {
+        use self::userdata::UserDataWorld;
+
+        let globals = self.lua.globals();
+
+        match globals.get::<_, Value>("ai_step") {
+            Ok(Value::Function(_)) => {}
+            _ => return Ok(()),
+        }
+
+        let set_controller = |controller: Option<usize>| {
+            let mut world: UserDataWorld = globals.get("__sky_world")?;
+            world.controller = controller;
+            globals.set("__sky_world", world)
+        };
+
+        for &faction in scripted {
+            set_controller(Some(faction))
+                .and_then(|_| globals.set("__sky_faction", faction))
+                .and_then(|_| {
+                    self.lua.exec::<()>(
+                        "ai_step(__sky_world, __sky_faction)",
+                        Some("calling ai_step"),
+                    )
+                })
+                .map_err(|e| {
+                    SkyError::lua(format!("calling ai_step for faction {}", faction), e, false)
+                })?;
+        }
+
+        set_controller(None).map_err(|e| SkyError::lua("calling ai_step", e, false))
+    }
+
+    pub fn add_lua(&mut self, src: &str) -> Result<(), SkyError> {
+        self.lua
+            .exec::<()>(src, Some("Loading Scenario Script File"))
+            .map_err(|e| SkyError::lua("executing the scenario description", e, true))
     }
 
     pub fn reset_rng_ptr(&mut self, world: &mut World) {
@@ -131,26 +413,42 @@ impl LuaSystem {
         &mut self,
         world: &mut World,
         path: P,
-    ) -> Result<(), Box<Error>> {
+    ) -> Result<(), SkyError> {
         use std::fs::File;
         use std::io::prelude::*;
-        use self::userdata::UserDataRng;
-        use rand::Isaac64Rng;
 
         let mut file = File::open(&path).or_else(|e| {
-            Err(format!(
+            Err(SkyError::Config(format!(
                 "Could not load Lua file, is the path right?:\n\t{}",
                 e
-            ))
+            )))
         })?;
         let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .expect("Could not read Lua contents");
+        file.read_to_string(&mut contents).or_else(|e| {
+            Err(SkyError::Config(format!(
+                "Could not read Lua file:\n\t{}",
+                e
+            )))
+        })?;
 
-        self.lua.exec::<()>(
+        self.init_from_source(
+            world,
             &contents,
-            Some(&format!("Lua Scenario Script at path {:?}", path)),
-        )?;
+            &format!("Lua Scenario Script at path {:?}", path),
+        )
+    }
+
+    /// Like `init`, but loads the scenario directly from its Lua source.
+    pub fn init_from_source(
+        &mut self,
+        world: &mut World,
+        src: &str,
+        name: &str,
+    ) -> Result<(), SkyError> {
+        use self::userdata::UserDataRng;
+        use rand::Isaac64Rng;
+
+        self.lua.exec::<()>(src, Some(name))?;
 
         let rng = &mut *world.write_resource::<Isaac64Rng>();
         let rng = UserDataRng { rng: rng };
@@ -160,12 +458,13 @@ impl LuaSystem {
         Ok(())
     }
 
-    pub fn reset(&mut self, world: &mut World) -> Result<(), Box<Error>> {
+    pub fn reset(&mut self, world: &mut World) -> Result<(), SkyError> {
         use engine::components::Pos;
         use engine::resources::UnitTypeMap;
 
         let units: Table = self.lua
-            .eval("sky_reset(__sky_rts_rng)", Some("Restart function"))?;
+            .eval("sky_reset(__sky_rts_rng)", Some("Restart function"))
+            .map_err(|e| SkyError::lua("calling sky_reset", e, true))?;
 
         for unit in units.sequence_values::<Table>() {
             let unit = unit?;
@@ -182,7 +481,9 @@ impl LuaSystem {
                 unit_types
                     .tag_map
                     .get(&template)
-                    .ok_or_else(|| format!("Could not get unit type template {}", template))?
+                    .ok_or_else(|| {
+                        SkyError::Config(format!("Could not get unit type template {}", template))
+                    })?
                     .clone()
             };
 
@@ -192,15 +493,18 @@ impl LuaSystem {
         Ok(())
     }
 
-    pub fn load_scenario(&mut self, world: &mut World) -> Result<(), Box<Error>> {
-        use engine::components::{FactionId, Shape};
-        use engine::resources::{Player, UnitType, UnitTypeMap, PLAYER_COLORS};
+    pub fn load_scenario(&mut self, world: &mut World) -> Result<(), SkyError> {
+        use engine::components::{FactionId, MoveBehavior, Pos, Shape};
+        use engine::resources::{AgentFactions, EpisodeLimits, FeatureLayers, ObservationGrid,
+                                OutcomeRewards, Player, UnitType, UnitTypeMap, Visibility,
+                                PLAYER_COLORS};
 
         let table: Table = self.lua
-            .eval("sky_init()", Some("Initializing in sky_init from Lua"))?;
+            .eval("sky_init()", Some("Initializing in sky_init from Lua"))
+            .map_err(|e| SkyError::lua("calling sky_init", e, true))?;
 
         let factions: usize = if table.contains_key("factions")? {
-            table.get("factions").unwrap()
+            table.get("factions")?
         } else {
             2
         };
@@ -215,6 +519,62 @@ impl LuaSystem {
             }
         }
 
+        if table.contains_key("observation")? {
+            let obs: Table = table.get("observation")?;
+            let grid = &mut *world.write_resource::<ObservationGrid>();
+
+            if obs.contains_key("width")? {
+                grid.width = obs.get("width")?;
+            }
+            if obs.contains_key("height")? {
+                grid.height = obs.get("height")?;
+            }
+            if obs.contains_key("cell_size")? {
+                grid.cell_size = obs.get("cell_size")?;
+            }
+            if obs.contains_key("origin")? {
+                let origin: Table = obs.get("origin")?;
+                grid.origin = Pos::new(origin.get("x")?, origin.get("y")?);
+            }
+        }
+
+        if table.contains_key("agent_factions")? {
+            world.write_resource::<AgentFactions>().0 = table.get("agent_factions")?;
+        }
+
+        if table.contains_key("scripted_factions")? {
+            world.write_resource::<ScriptedFactions>().0 = table.get("scripted_factions")?;
+        }
+
+        {
+            let rewards = &mut *world.write_resource::<OutcomeRewards>();
+            if table.contains_key("victory_reward")? {
+                rewards.victory = table.get("victory_reward")?;
+            }
+            if table.contains_key("failure_penalty")? {
+                rewards.failure = table.get("failure_penalty")?;
+            }
+        }
+
+        {
+            let limits = &mut *world.write_resource::<EpisodeLimits>();
+            if table.contains_key("max_episode_time")? {
+                limits.max_time = Some(table.get("max_episode_time")?);
+            }
+            if table.contains_key("max_steps")? {
+                limits.max_steps = Some(table.get("max_steps")?);
+            }
+        }
+
+        if table.contains_key("fog_of_war")? {
+            world.write_resource::<Visibility>().fog_of_war = table.get("fog_of_war")?;
+        }
+
+        if table.contains_key("feature_layers")? {
+            let names: Vec<String> = table.get("feature_layers")?;
+            *world.write_resource::<FeatureLayers>() = FeatureLayers::from_names(&names)?;
+        }
+
         {
             let unit_types: Table = table.get("unit_types")?;
 
@@ -289,6 +649,31 @@ impl LuaSystem {
                     } else {
                         default.attack_damage
                     },
+                    move_behavior: if unit_type.contains_key("move_behavior")? {
+                        let behavior: String = unit_type.get("move_behavior")?;
+                        match &*behavior {
+                            "straight" => MoveBehavior::Straight,
+                            "pathfind" => MoveBehavior::Pathfind,
+                            _ => {
+                                return Err(SkyError::Config(format!(
+                                    "Unknown move_behavior {}, expected \"straight\" or \"pathfind\"",
+                                    behavior
+                                )))
+                            }
+                        }
+                    } else {
+                        default.move_behavior
+                    },
+                    sight_range: if unit_type.contains_key("sight_range")? {
+                        unit_type.get("sight_range")?
+                    } else {
+                        default.sight_range
+                    },
+                    attributes: if unit_type.contains_key("attributes")? {
+                        unit_type.get("attributes")?
+                    } else {
+                        default.attributes.clone()
+                    },
                     ..UnitType::default()
                 };
 
@@ -322,3 +707,369 @@ impl LuaSystem {
         Ok(())
     }
 }
+
+/// Gives the rewards for the units that died this frame, only deaths
+/// involving the agent's units are rewarded.
+fn reward_deaths(sys_data: &mut LuaSystemData) -> Result<(), SkyError> {
+    use specs::Join;
+
+    for (faction, tag, death) in (&sys_data.faction, &sys_data.tag, &sys_data.death).join() {
+        // The killer may have died in the same frame
+        let killer_faction = match sys_data.faction.get(death.killer) {
+            Some(killer_faction) => killer_faction,
+            None => continue,
+        };
+
+        let u_type = sys_data
+            .unit_type
+            .tag_map
+            .get(&tag.0)
+            .ok_or_else(|| SkyError::Config(format!("Unknown unit type {}", tag.0)))?;
+
+        let agent_factions = &sys_data.agent_factions.0;
+        if agent_factions.contains(&faction.0) {
+            *sys_data.reward.0.entry("death".to_string()).or_insert(0.0) += u_type.death_penalty;
+        } else if agent_factions.contains(&killer_faction.0) {
+            *sys_data.reward.0.entry("kill".to_string()).or_insert(0.0) += u_type.kill_reward;
+        }
+    }
+
+    Ok(())
+}
+
+/// Calls the scenario's hook `name` with `args`, hooks the
+/// scenario doesn't define are skipped.
+fn call_hook<'lua, A>(lua: &'lua Lua, name: &str, args: A) -> Result<(), SkyError>
+where
+    A: ToLuaMulti<'lua>,
+{
+    let hook = match lua.globals().get::<_, Value>(name) {
+        Ok(Value::Function(hook)) => hook,
+        _ => return Ok(()),
+    };
+
+    hook.call::<_, ()>(args)
+        .map_err(|e| SkyError::lua(format!("calling {}", name), e, false))
+}
+
+fn spawn_unit(
+    world: &mut World,
+    unit_type: &str,
+    faction: usize,
+    pos: Pos,
+) -> Result<(), SkyError> {
+    use engine::resources::{Player, UnitTypeMap};
+
+    let spawn_error = |description: String| SkyError::Lua {
+        context: "spawning a unit".to_string(),
+        description,
+        fatal: false,
+    };
+
+    let factions = world.read_resource::<Vec<Player>>().len();
+    if faction >= factions {
+        return Err(spawn_error(format!(
+            "Faction {} does not exist, there are only {} factions",
+            faction, factions
+        )));
+    }
+
+    let template = {
+        let unit_types = world.read_resource::<UnitTypeMap>();
+
+        unit_types
+            .tag_map
+            .get(unit_type)
+            .ok_or_else(|| spawn_error(format!("Unknown unit type {}", unit_type)))?
+            .clone()
+    };
+
+    template.build_entity(world, pos, faction);
+
+    Ok(())
+}
+
+/// Removes the unit as though it died, without calling `on_death` or
+/// giving any rewards.
+fn remove_unit(world: &mut World, id: Index) -> Result<(), SkyError> {
+    let entity = world.entities().entity(id);
+    if !world.entities().is_alive(entity) {
+        return Err(SkyError::Lua {
+            context: "removing a unit".to_string(),
+            description: format!("Unit {} does not exist", id),
+            fatal: false,
+        });
+    }
+
+    let mut death = world.write::<Death>();
+    if death.get(entity).is_none() {
+        death.insert(entity, Death { killer: entity });
+    }
+
+    Ok(())
+}
+
+/// Takes a snapshot of the unit for Lua, if it's still around.
+fn unit_handle(sys_data: &LuaSystemData, id: Entity) -> Option<UserDataUnit> {
+    use engine::components::MoveTarget;
+
+    let faction = sys_data.faction.get(id)?;
+    let tag = sys_data.tag.get(id)?;
+    let pos = sys_data.pos.get(id)?;
+    let hp = sys_data.hp.get(id)?;
+
+    let (move_target, order_target) = match sys_data.orders.move_order(id).map(|m| m.target) {
+        Some(MoveTarget::Ground(pos)) => (Some(pos), None),
+        Some(MoveTarget::Unit(target)) => (None, Some(target.id())),
+        None => (None, None),
+    };
+
+    Some(UserDataUnit {
+        id: id.id(),
+        faction: *faction,
+        unit_type: tag.0.clone(),
+        pos: *pos,
+        hp: hp.curr_hp,
+        max_hp: hp.max_hp,
+        move_target,
+        order_target,
+        attacking: sys_data.orders.attack(id).map(|atk| atk.target.id()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use engine::test_util::{attack, inline_rts, move_to, orders, unit};
+    use scaii_defs::protos::Action;
+
+    #[test]
+    fn kill_rewards_go_to_the_agent_factions() {
+        use engine::resources::RtsState;
+
+        // The agent plays faction 1 and destroys a faction 0 tower
+        let mut rts = inline_rts(
+            "agent_factions = { 1 },",
+            r#"
+            { unit_type = "soldier", faction = 1, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 0, pos = { x = 130.0, y = 100.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+        rts.step(orders(vec![attack(0, 1)]));
+
+        let mut rewards = None;
+        for _ in 0..1000 {
+            rts.step(Action::default());
+
+            let state = rts.world.read_resource::<RtsState>();
+            if !state.0.typed_reward.is_empty() {
+                rewards = Some(state.0.typed_reward.clone());
+                break;
+            }
+        }
+
+        let rewards = rewards.expect("The tower was never destroyed");
+        assert_eq!(rewards.get("kill"), Some(&50.0));
+        assert_eq!(rewards.get("death"), None);
+    }
+
+    #[test]
+    fn ai_step_orders_its_own_faction() {
+        use engine::components::{Move, Pos};
+        use engine::resources::Errors;
+
+        let mut rts = inline_rts(
+            "scripted_factions = { 1 },",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "soldier", faction = 1, pos = { x = 400.0, y = 400.0 } },
+            "#,
+            r#"
+            function ai_step(world, faction)
+                for _, unit in ipairs(world:units()) do
+                    world:move(unit:id(), 300.0, 400.0)
+                end
+            end
+            "#,
+        );
+
+        rts.reset().unwrap();
+        rts.world.write_resource::<Errors>().0.clear();
+        rts.step(Action::default());
+
+        // The agent's soldier can't be ordered by the script
+        assert_eq!(rts.world.read_resource::<Errors>().0.len(), 1);
+        assert!(rts.world.read::<Move>().get(unit(&rts, 0)).is_none());
+        assert!(rts.world.read::<Move>().get(unit(&rts, 1)).is_some());
+
+        rts.step(Action::default());
+        assert!(rts.world.read::<Pos>().get(unit(&rts, 1)).unwrap().x < 400.0);
+        assert_eq!(
+            *rts.world.read::<Pos>().get(unit(&rts, 0)).unwrap(),
+            Pos::new(100.0, 100.0)
+        );
+    }
+
+    #[test]
+    fn the_world_is_only_built_for_hooks() {
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+        rts.step(Action::default());
+        assert!(rts.lua_sys.add_lua("assert(__sky_world == nil)").is_ok());
+
+        rts.lua_sys.add_lua("function on_tick(world, dt) end").unwrap();
+        rts.step(Action::default());
+        assert!(rts.lua_sys.add_lua("assert(__sky_world ~= nil)").is_ok());
+    }
+
+    #[test]
+    fn hooks_run_in_order() {
+        use engine::resources::{Errors, Terminal};
+
+        let hook = |name: &str| {
+            format!(
+                "function {}(world) table.insert(calls, \"{}\") end\n",
+                name, name
+            )
+        };
+        let hooks: String = [
+            "on_tick",
+            "on_spawn",
+            "on_order_issued",
+            "on_attack_start",
+            "on_damage",
+            "on_death",
+            "ai_step",
+            "on_timeout",
+        ].iter()
+            .map(|name| hook(*name))
+            .collect();
+
+        // The episode is cut off on the first frame after the reset
+        let mut rts = inline_rts(
+            "scripted_factions = { 1 }, max_steps = 2,",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 130.0, y = 100.0 } },
+            "#,
+            &format!(
+                r#"
+                calls = {{}}
+                {}
+                function on_episode_end(world, winner)
+                    table.insert(calls, "on_episode_end")
+                    local got = table.concat(calls, ",")
+                    if got ~= "on_tick,on_spawn,on_spawn,ai_step,"
+                        .. "on_tick,on_order_issued,on_attack_start,on_damage,ai_step,"
+                        .. "on_timeout,on_episode_end" then
+                        error("Hooks were called as " .. got)
+                    end
+                end
+                "#,
+                hooks
+            ),
+        );
+
+        rts.reset().unwrap();
+        rts.step(orders(vec![attack(0, 1)]));
+
+        assert_eq!(rts.world.read_resource::<Errors>().0, vec![]);
+        assert!(rts.world.read_resource::<Terminal>().0);
+    }
+
+    #[test]
+    fn scripts_spawn_and_remove_units() {
+        use engine::components::{FactionId, Pos, UnitTypeTag};
+        use engine::resources::{Errors, Render};
+        use specs::Join;
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 130.0, y = 100.0 } },
+            "#,
+            r#"
+            function on_order_issued(world, unit)
+                world:spawn("tower", 1, { x = 300.0, y = 300.0 })
+                world:spawn("tower", 5, { x = 300.0, y = 300.0 })
+                world:remove(1)
+            end
+            "#,
+        );
+
+        rts.reset().unwrap();
+        rts.step(orders(vec![move_to(0, 100.0, 200.0)]));
+
+        // Spawning for a faction that doesn't exist fails on its own
+        assert_eq!(rts.world.read_resource::<Errors>().0.len(), 1);
+        assert!(!rts.world.entities().is_alive(unit(&rts, 1)));
+
+        let units: Vec<_> = (
+            &*rts.world.entities(),
+            &rts.world.read::<Pos>(),
+            &rts.world.read::<FactionId>(),
+            &rts.world.read::<UnitTypeTag>(),
+        ).join()
+            .map(|(id, pos, faction, tag)| (id.id(), *pos, faction.0, tag.0.clone()))
+            .collect();
+        assert_eq!(units.len(), 2);
+
+        let spawned = units.iter().find(|unit| unit.0 != 0).unwrap();
+        assert_eq!(
+            (spawned.1, spawned.2, &*spawned.3),
+            (Pos::new(300.0, 300.0), 1, "tower")
+        );
+
+        // Units are sent in full on the frame they're spawned
+        let render = rts.world.read_resource::<Render>();
+        let rendered = render
+            .0
+            .entities
+            .iter()
+            .find(|entity| entity.id == spawned.0 as u64)
+            .unwrap();
+        assert!(!rendered.shapes.is_empty());
+    }
+
+    #[test]
+    fn outcome_rewards_depend_on_the_winner() {
+        use engine::resources::RtsState;
+
+        let outcome_rewards = |winner: usize| {
+            let mut rts = inline_rts(
+                "victory_reward = 10, failure_penalty = -10,",
+                r#"
+                { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+                { unit_type = "soldier", faction = 1, pos = { x = 400.0, y = 400.0 } },
+                "#,
+                &format!(
+                    "function on_order_issued(world, unit) world:victory({}) end",
+                    winner
+                ),
+            );
+
+            rts.reset().unwrap();
+            rts.step(orders(vec![move_to(0, 200.0, 100.0)]));
+
+            let state = rts.world.read_resource::<RtsState>();
+            assert!(state.0.terminal);
+
+            let mut rewards: Vec<_> = state.0.typed_reward.clone().into_iter().collect();
+            rewards.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            rewards
+        };
+
+        assert_eq!(outcome_rewards(0), vec![("victory".to_string(), 10.0)]);
+        assert_eq!(outcome_rewards(1), vec![("failure".to_string(), -10.0)]);
+    }
+}
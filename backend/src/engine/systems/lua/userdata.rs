@@ -1,9 +1,16 @@
-use rlua::{UserData, UserDataMethods};
+use rlua::{Error as LuaError, Table, UserData, UserDataMethods};
 
-use engine::components::FactionId;
+use specs::Index;
+
+use engine::components::{FactionId, Pos};
+use engine::resources::{Outcome, SkyCollisionWorld};
+use engine::systems::input::{Order, OrderTarget};
 
 use rand::Rng;
 
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
 pub struct UserDataRng<R: Rng + 'static> {
     pub rng: *mut R,
 }
@@ -20,32 +27,292 @@ impl<R: Rng + 'static> UserData for UserDataRng<R> {
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default, Debug)]
+/// The units alive at the start of the frame's Lua callbacks.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct WorldView {
+    /// Keyed by ID, so they're always listed in ID order
+    pub units: BTreeMap<Index, UserDataUnit>,
+    /// Only `None` for an empty view, see `new`
+    col_world: Option<*const SkyCollisionWorld>,
+}
+
+impl WorldView {
+    /// The view keeps a pointer to `col_world` for `units_in_radius`. Like
+    /// the RNG given to Lua, it points at a resource boxed in the `World`
+    /// that's only ever overwritten in place, and Lua only runs while the
+    /// `LuaSystem` holds it, so it's valid whenever the view is queried.
+    pub fn new(units: Vec<UserDataUnit>, col_world: &SkyCollisionWorld) -> Self {
+        WorldView {
+            units: units.into_iter().map(|unit| (unit.id, unit)).collect(),
+            col_world: Some(col_world),
+        }
+    }
+
+    pub fn unit(&self, id: Index) -> Option<&UserDataUnit> {
+        self.units.get(&id)
+    }
+
+    /// The units whose colliders overlap the circle, in ID order.
+    pub fn units_in_radius(&self, center: &Pos, radius: f64) -> Vec<UserDataUnit> {
+        use nalgebra::{self, Isometry2, Vector2};
+        use ncollide::query;
+        use ncollide::shape::Ball;
+        use engine::resources::COLLISION_SCALE;
+
+        let c_world = match self.col_world {
+            // See `new`
+            Some(c_world) => unsafe { &*c_world },
+            None => return vec![],
+        };
+
+        let ball = Ball::new(radius / COLLISION_SCALE);
+        let ball_pos = Isometry2::new(
+            Vector2::new(center.x / COLLISION_SCALE, center.y / COLLISION_SCALE),
+            nalgebra::zero(),
+        );
+
+        // Every collider is checked rather than going through the broad
+        // phase, which isn't updated again after units are pushed apart
+        let mut ids: Vec<Index> = c_world
+            .collision_objects()
+            .filter(|obj| !obj.data().detector)
+            .filter(|obj| {
+                query::distance(&ball_pos, &ball, obj.position(), obj.shape().as_ref()) <= 0.0
+            })
+            .map(|obj| obj.data().e.id())
+            .collect();
+        ids.sort();
+
+        // Units that died this frame are still in the collision world
+        ids.into_iter()
+            .filter_map(|id| self.units.get(&id).cloned())
+            .collect()
+    }
+
+    pub fn alive_count(&self, faction: usize) -> usize {
+        self.units
+            .values()
+            .filter(|unit| unit.faction.0 == faction)
+            .count()
+    }
+}
+
+/// Read-only queries over the world, shared by `UserDataWorld`
+/// and `UserDataReadWorld`.
+pub trait ReadWorld {
+    fn view(&self) -> &WorldView;
+}
+
+fn add_query_methods<T: UserData + ReadWorld>(methods: &mut UserDataMethods<T>) {
+    methods.add_method("units", |_, this, ()| {
+        Ok(this.view().units.values().cloned().collect::<Vec<_>>())
+    });
+
+    methods.add_method("units_of", |_, this, faction: usize| {
+        Ok(this.view()
+            .units
+            .values()
+            .filter(|unit| unit.faction.0 == faction)
+            .cloned()
+            .collect::<Vec<_>>())
+    });
+
+    methods.add_method("unit", |_, this, id: Index| Ok(this.view().unit(id).cloned()));
+
+    methods.add_method("units_in_radius", |_, this, (x, y, radius): (f64, f64, f64)| {
+        Ok(this.view().units_in_radius(&Pos::new(x, y), radius))
+    });
+
+    methods.add_method("alive_count", |_, this, faction: usize| {
+        Ok(this.view().alive_count(faction))
+    });
+}
+
+#[derive(Clone, PartialEq, Default, Debug)]
 pub struct UserDataWorld {
-    pub victory: Option<FactionId>,
+    /// Set when a callback ends the episode
+    pub outcome: Option<Outcome>,
+    pub view: Rc<WorldView>,
+    /// The faction being controlled by `ai_step`, if it's running
+    pub controller: Option<usize>,
+    /// Orders given by `ai_step`, carried out after the callbacks return
+    pub orders: Vec<Order>,
+    /// Units spawned or removed by the callbacks, these can only be
+    /// carried out once the Lua system is done with the world
+    pub changes: Vec<WorldChange>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum WorldChange {
+    Spawn {
+        unit_type: String,
+        faction: usize,
+        pos: Pos,
+    },
+    Remove(Index),
+}
+
+impl UserDataWorld {
+    fn order(&mut self, unit_id: Index, target: OrderTarget) -> Result<(), LuaError> {
+        match self.controller {
+            Some(faction) => {
+                self.orders.push(Order {
+                    unit_id,
+                    target,
+                    faction: Some(faction),
+                });
+                Ok(())
+            }
+            None => Err(LuaError::RuntimeError(
+                "Orders can only be given from ai_step".to_string(),
+            )),
+        }
+    }
+}
+
+impl ReadWorld for UserDataWorld {
+    fn view(&self) -> &WorldView {
+        &self.view
+    }
 }
 
 impl UserData for UserDataWorld {
     fn add_methods(methods: &mut UserDataMethods<Self>) {
+        add_query_methods(methods);
+
         methods.add_method_mut("victory", |_, this, faction: usize| {
-            this.victory = Some(FactionId(faction));
+            this.outcome = Some(Outcome::Victory(faction));
+            Ok(())
+        });
+
+        methods.add_method_mut("draw", |_, this, ()| {
+            this.outcome = Some(Outcome::Draw);
+            Ok(())
+        });
+
+        methods.add_method_mut("move", |_, this, (unit, x, y): (Index, f64, f64)| {
+            this.order(unit, OrderTarget::Move(Pos::new(x, y)))
+        });
+
+        methods.add_method_mut("attack", |_, this, (unit, target): (Index, Index)| {
+            this.order(unit, OrderTarget::Attack(target))
+        });
+
+        methods.add_method_mut(
+            "spawn",
+            |_, this, (unit_type, faction, pos): (String, usize, Table)| {
+                this.changes.push(WorldChange::Spawn {
+                    unit_type,
+                    faction,
+                    pos: Pos::new(pos.get("x")?, pos.get("y")?),
+                });
+                Ok(())
+            },
+        );
+
+        methods.add_method_mut("remove", |_, this, unit: Index| {
+            this.changes.push(WorldChange::Remove(unit));
             Ok(())
         });
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default, Debug)]
-pub struct UserDataReadWorld;
+/// The world as seen by `skip_lua` predicates, which may query it
+/// but not change it.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct UserDataReadWorld {
+    pub view: Rc<WorldView>,
+}
+
+impl ReadWorld for UserDataReadWorld {
+    fn view(&self) -> &WorldView {
+        &self.view
+    }
+}
 
-impl UserData for UserDataReadWorld {}
+impl UserData for UserDataReadWorld {
+    fn add_methods(methods: &mut UserDataMethods<Self>) {
+        add_query_methods(methods);
+    }
+}
 
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default, Debug)]
+/// A unit as it was at the start of the frame's Lua callbacks.
+#[derive(Clone, PartialEq, Debug)]
 pub struct UserDataUnit {
+    pub id: Index,
     pub faction: FactionId,
+    pub unit_type: String,
+    pub pos: Pos,
+    pub hp: f64,
+    pub max_hp: f64,
+    /// Where the unit was ordered to move, if anywhere
+    pub move_target: Option<Pos>,
+    /// The unit it was ordered to attack, if any
+    pub order_target: Option<Index>,
+    /// The unit it's currently attacking, if any
+    pub attacking: Option<Index>,
 }
 
 impl UserData for UserDataUnit {
     fn add_methods(methods: &mut UserDataMethods<Self>) {
+        methods.add_method("id", |_, this, ()| Ok(this.id));
         methods.add_method("faction", |_, this, ()| Ok(this.faction.0));
+        methods.add_method("unit_type", |_, this, ()| Ok(this.unit_type.clone()));
+        methods.add_method("pos", |_, this, ()| Ok((this.pos.x, this.pos.y)));
+        methods.add_method("hp", |_, this, ()| Ok(this.hp));
+        methods.add_method("max_hp", |_, this, ()| Ok(this.max_hp));
+
+        // Returns nothing if the unit has no move order
+        methods.add_method("move_target", |_, this, ()| {
+            Ok(match this.move_target {
+                Some(pos) => (Some(pos.x), Some(pos.y)),
+                None => (None, None),
+            })
+        });
+        methods.add_method("order_target", |_, this, ()| Ok(this.order_target));
+        methods.add_method("attacking", |_, this, ()| Ok(this.attacking));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use engine::test_util::{error_count, inline_rts};
+
+    #[test]
+    fn world_queries_see_the_units() {
+        use engine::resources::RtsState;
+
+        // Any failed assertion is reported as a Lua error
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 135.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 300.0, y = 300.0 } },
+            "#,
+            r#"
+            function on_tick(world, dt)
+                assert(#world:units() == 3)
+                assert(#world:units_of(1) == 2)
+                assert(world:alive_count(0) == 1)
+                assert(world:unit(1):unit_type() == "tower")
+                assert(world:unit(7) == nil)
+
+                local near = world:units_in_radius(100.0, 100.0, 40.0)
+                assert(#near == 2 and near[1]:id() == 0 and near[2]:id() == 1)
+
+                -- The tower's collider starts 25 units away
+                assert(#world:units_in_radius(100.0, 100.0, 24.0) == 1)
+                assert(#world:units_in_radius(100.0, 100.0, 26.0) == 2)
+
+                world:victory(0)
+            end
+            "#,
+        );
+
+        let mm = rts.reset().unwrap();
+
+        assert_eq!(error_count(&mm), 0);
+        assert!(rts.world.read_resource::<RtsState>().0.terminal);
     }
 }
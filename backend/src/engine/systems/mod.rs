@@ -6,6 +6,8 @@ pub mod attack;
 pub mod collision;
 pub mod cleanup;
 pub mod state;
+pub mod state_update;
+pub mod visibility;
 
 pub use self::movement::MoveSystem;
 pub use self::proto_render::RenderSystem;
@@ -15,3 +17,5 @@ pub use self::attack::AttackSystem;
 pub use self::collision::CollisionSystem;
 pub use self::cleanup::CleanupSystem;
 pub use self::state::StateBuildSystem;
+pub use self::state_update::StateUpdateSystem;
+pub use self::visibility::VisibilitySystem;
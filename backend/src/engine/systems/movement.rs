@@ -1,5 +1,7 @@
-use specs::{Entities, Entity, Fetch, ReadStorage, System, WriteStorage};
-use engine::components::{Move, MoveBehavior, MoveTarget, MovedFlag, Pos, Speed};
+use specs::{Entities, Entity, Fetch, FetchMut, ReadStorage, System, WriteStorage};
+use engine::components::{Move, MoveBehavior, MoveTarget, MovedFlag, NavPath, Pos, Shape, Speed,
+                         Static};
+use engine::resources::NavGrid;
 use engine::DeltaT;
 
 #[derive(SystemData)]
@@ -8,6 +10,10 @@ pub struct MoveSystemData<'a> {
     speeds: ReadStorage<'a, Speed>,
     moves: WriteStorage<'a, Move>,
     moved: WriteStorage<'a, MovedFlag>,
+    paths: WriteStorage<'a, NavPath>,
+    shapes: ReadStorage<'a, Shape>,
+    statics: ReadStorage<'a, Static>,
+    nav: FetchMut<'a, NavGrid>,
     delta_t: Fetch<'a, DeltaT>,
     ids: Entities<'a>,
 }
@@ -16,13 +22,15 @@ pub struct MoveSystemData<'a> {
 pub struct MoveSystem {
     // Reduce allocations by caching the largest the list
     // of deferred target seeks has ever been
-    target_cache: Vec<(Entity, Entity)>,
+    target_cache: Vec<(Entity, Entity, MoveBehavior)>,
+    arrived_cache: Vec<Entity>,
 }
 
 impl MoveSystem {
     pub fn new() -> Self {
         MoveSystem {
             target_cache: Vec::with_capacity(100),
+            arrived_cache: Vec::with_capacity(100),
         }
     }
 }
@@ -33,7 +41,17 @@ impl<'a> System<'a> for MoveSystem {
     fn run(&mut self, mut sys_data: Self::SystemData) {
         use specs::Join;
 
+        if sys_data.nav.dirty {
+            sys_data
+                .nav
+                .rebuild((&sys_data.positions, &sys_data.shapes, &sys_data.statics)
+                    .join()
+                    .map(|(pos, shape, _)| (pos, shape)));
+        }
+
         let targets = &mut self.target_cache;
+        let arrived = &mut self.arrived_cache;
+        let delta_t = sys_data.delta_t.0;
 
         for (pos, moves, speed, id) in (
             &mut sys_data.positions,
@@ -49,21 +67,39 @@ impl<'a> System<'a> for MoveSystem {
                 // (we can't get a position while iterating over positions!)
                 Move {
                     target: MoveTarget::Unit(target),
-                    ..
+                    behavior,
                 } => {
-                    targets.push((id, target));
+                    targets.push((id, target, behavior));
                     continue;
                 }
                 Move {
                     target: MoveTarget::Ground(ref tar_pos),
                     ref behavior,
-                } => move_ground(pos, tar_pos, behavior, sys_data.delta_t.0, speed.0),
+                } => {
+                    let mut mover = Mover {
+                        id,
+                        nav: &*sys_data.nav,
+                        paths: &mut sys_data.paths,
+                        delta_t,
+                        speed: speed.0,
+                    };
+
+                    if mover.move_ground(pos, tar_pos, behavior) {
+                        arrived.push(id);
+                    }
+                }
             }
         }
 
-        for (id, target) in targets.drain(..) {
+        for id in arrived.drain(..) {
+            sys_data.moves.remove(id);
+            sys_data.paths.remove(id);
+        }
+
+        for (id, target, behavior) in targets.drain(..) {
             if !sys_data.ids.is_alive(target) {
-                sys_data.moves.remove(target);
+                sys_data.moves.remove(id);
+                sys_data.paths.remove(id);
                 continue;
             }
             let tar_pos = match sys_data.positions.get(target) {
@@ -73,37 +109,117 @@ impl<'a> System<'a> for MoveSystem {
             let pos = sys_data.positions.get_mut(id).unwrap();
             let speed = sys_data.speeds.get(id).unwrap();
 
-            move_ground(
-                pos,
-                &tar_pos,
-                &MoveBehavior::Straight,
-                sys_data.delta_t.0,
-                speed.0,
-            )
+            let mut mover = Mover {
+                id,
+                nav: &*sys_data.nav,
+                paths: &mut sys_data.paths,
+                delta_t,
+                speed: speed.0,
+            };
+
+            mover.move_ground(pos, &tar_pos, &behavior);
         }
     }
 }
 
-fn move_ground(pos: &mut Pos, tar_pos: &Pos, behavior: &MoveBehavior, delta_t: f64, speed: f64) {
-    match *behavior {
-        MoveBehavior::Straight => {
-            let dir = **tar_pos - **pos;
-
-            let mut new_pos = **pos + (dir.normalize() * delta_t * speed);
+/// The per-unit state needed to advance a single move order.
+struct Mover<'a, 'b: 'a> {
+    id: Entity,
+    nav: &'a NavGrid,
+    paths: &'a mut WriteStorage<'b, NavPath>,
+    delta_t: f64,
+    speed: f64,
+}
 
-            let new_dir = **tar_pos - new_pos;
+impl<'a, 'b> Mover<'a, 'b> {
+    /// Moves `pos` toward `tar_pos`, returning whether the target
+    /// has been reached.
+    fn move_ground(&mut self, pos: &mut Pos, tar_pos: &Pos, behavior: &MoveBehavior) -> bool {
+        match *behavior {
+            MoveBehavior::Straight => step_toward(pos, tar_pos, self.delta_t, self.speed),
+            MoveBehavior::Pathfind => {
+                // Long ticks can carry the unit past several waypoints
+                let mut time_left = self.delta_t;
+                loop {
+                    let waypoint = self.next_waypoint(pos, tar_pos);
+                    let dist = (*waypoint - **pos).norm();
+
+                    let reached = step_toward(pos, &waypoint, time_left, self.speed);
+                    if !reached || waypoint == *tar_pos {
+                        return reached && waypoint == *tar_pos;
+                    }
+
+                    time_left -= dist / self.speed;
+                    if time_left <= 0.0 {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
 
-            /* Simple overshoot detection */
+    /// Gets the waypoint to head toward, recomputing the path if the
+    /// goal has moved to another cell or the obstacles have changed.
+    fn next_waypoint(&mut self, pos: &Pos, tar_pos: &Pos) -> Pos {
+        let goal_cell = self.nav.cell(tar_pos);
 
-            if dir[0].signum() != new_dir[0].signum() {
-                new_pos[0] = tar_pos[0];
+        let stale = match self.paths.get(self.id) {
+            Some(path) => {
+                path.generation != self.nav.generation || path.goal_cell != goal_cell
+                    || path.waypoints.is_empty()
             }
+            None => true,
+        };
+
+        if stale {
+            self.paths.insert(
+                self.id,
+                NavPath {
+                    waypoints: self.nav.find_path(pos, tar_pos),
+                    goal_cell,
+                    generation: self.nav.generation,
+                },
+            );
+        }
 
-            if dir[1].signum() != new_dir[1].signum() {
-                new_pos[1] = tar_pos[1];
-            }
+        let path = self.paths.get_mut(self.id).unwrap();
 
-            **pos = new_pos;
+        // The goal can move within its cell (e.g. a unit being chased)
+        path.waypoints[0] = *tar_pos;
+
+        while path.waypoints.len() > 1 && path.waypoints[path.waypoints.len() - 1] == *pos {
+            path.waypoints.pop();
         }
+
+        path.waypoints[path.waypoints.len() - 1]
+    }
+}
+
+/// Moves `pos` in a straight line toward `tar_pos`, returning whether
+/// it has been reached.
+fn step_toward(pos: &mut Pos, tar_pos: &Pos, delta_t: f64, speed: f64) -> bool {
+    // Normalizing a zero direction yields NaN
+    if *pos == *tar_pos {
+        return true;
     }
+
+    let dir = **tar_pos - **pos;
+
+    let mut new_pos = **pos + (dir.normalize() * delta_t * speed);
+
+    let new_dir = **tar_pos - new_pos;
+
+    /* Simple overshoot detection */
+
+    if dir[0].signum() != new_dir[0].signum() {
+        new_pos[0] = tar_pos[0];
+    }
+
+    if dir[1].signum() != new_dir[1].signum() {
+        new_pos[1] = tar_pos[1];
+    }
+
+    **pos = new_pos;
+
+    *pos == *tar_pos
 }
@@ -1,10 +1,12 @@
-use specs::{Entities, Fetch, FetchMut, Join, ReadStorage, System};
+use specs::{Entities, Entity, Fetch, FetchMut, Join, ReadStorage, System};
 use engine::components::{Color, Death, MovedFlag, Pos, Shape};
 use engine::{NeedsKeyInfo, Render};
 use engine::resources::Skip;
 
 use scaii_defs::protos::Entity as ScaiiEntity;
 
+use std::collections::HashSet;
+
 #[derive(SystemData)]
 pub struct RenderSystemData<'a> {
     complete_rerender: Fetch<'a, NeedsKeyInfo>,
@@ -19,14 +21,33 @@ pub struct RenderSystemData<'a> {
     out: FetchMut<'a, Render>,
 }
 
-#[derive(Default, Copy, Clone, Debug)]
-pub struct RenderSystem {}
+/// Renders every unit when `NeedsKeyInfo` is set, otherwise only
+/// the units that moved or died since the last rendered frame.
+///
+/// Skipped frames aren't rendered, and their moves and deaths are
+/// cleaned up before the next frame, so they're remembered here for
+/// the next delta instead.
+#[derive(Default, Clone, Debug)]
+pub struct RenderSystem {
+    skipped_moves: HashSet<Entity>,
+    skipped_deaths: HashSet<Entity>,
+}
+
+impl RenderSystem {
+    pub fn new() -> Self {
+        RenderSystem {
+            skipped_moves: HashSet::with_capacity(100),
+            skipped_deaths: HashSet::with_capacity(100),
+        }
+    }
+}
 
 impl<'a> System<'a> for RenderSystem {
     type SystemData = RenderSystemData<'a>;
 
     fn run(&mut self, sys_data: Self::SystemData) {
         if sys_data.skip.0 {
+            self.remember_skipped(&sys_data);
             return;
         }
         if sys_data.complete_rerender.0 {
@@ -38,17 +59,41 @@ impl<'a> System<'a> for RenderSystem {
 }
 
 impl RenderSystem {
+    fn remember_skipped(&mut self, sys_data: &RenderSystemData) {
+        for (_, id) in (&sys_data.moved, &*sys_data.ids).join() {
+            self.skipped_moves.insert(id);
+        }
+
+        for (_, id) in (&sys_data.death, &*sys_data.ids).join() {
+            self.skipped_moves.remove(&id);
+            self.skipped_deaths.insert(id);
+        }
+    }
+
     fn render_delta(&mut self, mut sys_data: RenderSystemData) {
         let out = &mut sys_data.out.0;
         out.entities.clear();
 
+        // Units that died while skipping are already gone
+        for id in self.skipped_deaths.drain() {
+            out.entities.push(ScaiiEntity {
+                id: id.id() as u64,
+                pos: None,
+                delete: true,
+                shapes: vec![],
+            });
+        }
+
         for (pos, id) in (
             &sys_data.pos,
             &*sys_data.ids,
             // &sys_data.moved, // Just a filter
         ).join()
         {
-            if !sys_data.moved.get(id).is_some() || !sys_data.death.get(id).is_some() {
+            let skipped_move = self.skipped_moves.contains(&id);
+            if !sys_data.moved.get(id).is_some() && !sys_data.death.get(id).is_some()
+                && !skipped_move
+            {
                 continue;
             }
 
@@ -61,12 +106,18 @@ impl RenderSystem {
 
             out.entities.push(entity);
         }
+
+        self.skipped_moves.clear();
     }
 
     fn render_all(&mut self, mut sys_data: RenderSystemData) {
         let out = &mut sys_data.out.0;
         out.entities.clear();
 
+        // Everything still around is about to be sent in full
+        self.skipped_moves.clear();
+        self.skipped_deaths.clear();
+
         for (color, pos, shape, id) in (
             &sys_data.color,
             &sys_data.pos,
@@ -90,3 +141,84 @@ impl RenderSystem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use engine::test_util::{inline_rts, move_to, orders, unit};
+
+    #[test]
+    fn render_delta_includes_moved_and_removed_units() {
+        use engine::resources::Render;
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 300.0, y = 300.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 300.0, y = 100.0 } },
+            "#,
+            r#"
+            function on_order_issued(world, unit)
+                world:remove(1)
+            end
+            "#,
+        );
+
+        rts.reset().unwrap();
+        rts.step(orders(vec![move_to(0, 200.0, 100.0)]));
+
+        let render = rts.world.read_resource::<Render>();
+        let mut entities: Vec<_> = render
+            .0
+            .entities
+            .iter()
+            .map(|entity| (entity.id, entity.delete, entity.shapes.is_empty()))
+            .collect();
+        entities.sort();
+
+        // The tower that did nothing isn't mentioned
+        assert_eq!(entities, vec![(0, false, true), (1, true, true)]);
+    }
+
+    #[test]
+    fn render_delta_catches_up_on_skipped_frames() {
+        use engine::components::Pos;
+        use engine::resources::Render;
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 300.0, y = 300.0 } },
+            "#,
+            r#"
+            function on_order_issued(world, unit)
+                world:remove(1)
+            end
+            "#,
+        );
+        rts.action_repeat = Some(3);
+
+        rts.reset().unwrap();
+        // The soldier arrives and the tower is removed on the first,
+        // skipped, frame and nothing changes after that
+        rts.step(orders(vec![move_to(0, 100.5, 100.0)]));
+
+        let render = rts.world.read_resource::<Render>();
+        let mut entities: Vec<_> = render
+            .0
+            .entities
+            .iter()
+            .map(|entity| (entity.id, entity.delete))
+            .collect();
+        entities.sort();
+        assert_eq!(entities, vec![(0, false), (1, true)]);
+
+        let soldier = *rts.world.read::<Pos>().get(unit(&rts, 0)).unwrap();
+        let rendered = render.0.entities.iter().find(|entity| entity.id == 0);
+        assert_eq!(
+            rendered.and_then(|entity| entity.pos.clone()),
+            Some(soldier.to_scaii_pos())
+        );
+    }
+}
@@ -1,31 +1,90 @@
-use specs::{Fetch, FetchMut, ReadStorage, System};
-use engine::components::{FactionId, Hp, UnitTypeTag};
-use engine::resources::{Reward, RtsState, Skip, SkyCollisionWorld, Terminal, UnitTypeMap,
-                        STATE_SCALE, STATE_SIZE};
+use specs::{Entities, Entity, Fetch, FetchMut, ReadStorage, System};
+use engine::components::{Attack, CollisionHandle, Death, FactionId, Hp, Movable, Move, Pos,
+                         Shape, Speed, UnitTypeTag};
+use engine::resources::{EpisodeOutcome, FeatureLayer, FeatureLayers, LastSeen, ObservationGrid,
+                        Reward, RtsState, Skip, Terminal, UnitTypeMap, Visibility, STATE_SIZE};
 use ndarray::Array3;
 
+use std::collections::HashMap;
+
 #[derive(SystemData)]
 pub struct StateBuildSystemData<'a> {
+    pos: ReadStorage<'a, Pos>,
+    shape: ReadStorage<'a, Shape>,
+    c_handle: ReadStorage<'a, CollisionHandle>,
+    death: ReadStorage<'a, Death>,
     hp: ReadStorage<'a, Hp>,
     faction: ReadStorage<'a, FactionId>,
-    collision_sys: Fetch<'a, SkyCollisionWorld>,
     tag: ReadStorage<'a, UnitTypeTag>,
+    speed: ReadStorage<'a, Speed>,
+    movable: ReadStorage<'a, Movable>,
+    moves: ReadStorage<'a, Move>,
+    attack: ReadStorage<'a, Attack>,
+    ids: Entities<'a>,
     unit_types: Fetch<'a, UnitTypeMap>,
     terminal: Fetch<'a, Terminal>,
+    outcome: Fetch<'a, EpisodeOutcome>,
     skip: Fetch<'a, Skip>,
+    grid: Fetch<'a, ObservationGrid>,
+    layers: Fetch<'a, FeatureLayers>,
+    visibility: Fetch<'a, Visibility>,
 
+    last_seen: FetchMut<'a, LastSeen>,
     state: FetchMut<'a, RtsState>,
     reward: FetchMut<'a, Reward>,
 }
 
+/// Where a unit was when it was last rasterized, and the cells it covered.
+struct Footprint {
+    pos: Pos,
+    shape: Shape,
+    cells: Vec<usize>,
+}
+
+/// Rasterizes every unit's collider into the state's features.
+///
+/// A cell belongs to a unit if its sample point lies within the unit's
+/// collider, exactly as if the collision world were queried at that point.
+/// Footprints are only recomputed for units that have moved, spawned
+/// or died since the last frame. Where units overlap the cell goes to
+/// the unit with the lowest ID.
+///
+/// Under fog of war, cells holding enemies none of the agent's
+/// factions can see are left empty.
 pub struct StateBuildSystem {
     state_cache: Array3<f64>,
+    grid: ObservationGrid,
+    /// The units covering each cell, indexed by `i * height + j`
+    coverage: Vec<Vec<Entity>>,
+    footprints: HashMap<Entity, Footprint>,
+    values_cache: Vec<f64>,
 }
 
 impl StateBuildSystem {
     pub fn new() -> Self {
         StateBuildSystem {
             state_cache: Array3::zeros([STATE_SIZE, STATE_SIZE, 4]),
+            grid: ObservationGrid::default(),
+            coverage: vec![Vec::new(); STATE_SIZE * STATE_SIZE],
+            footprints: HashMap::with_capacity(100),
+            values_cache: Vec::new(),
+        }
+    }
+
+    /// Removes `id` from every cell it covered, zeroing cells left empty.
+    fn erase(&mut self, id: Entity, footprint: &Footprint) {
+        let height = self.grid.height;
+
+        for &idx in &footprint.cells {
+            let cover = &mut self.coverage[idx];
+            cover.retain(|e| *e != id);
+
+            if cover.is_empty() {
+                let (i, j) = (idx / height, idx % height);
+                for k in 0..self.state_cache.shape()[2] {
+                    self.state_cache[(i, j, k)] = 0.0;
+                }
+            }
         }
     }
 }
@@ -34,61 +93,171 @@ impl<'a> System<'a> for StateBuildSystem {
     type SystemData = StateBuildSystemData<'a>;
 
     fn run(&mut self, mut sys_data: Self::SystemData) {
-        use nalgebra::Point2;
-        use ncollide::world::CollisionGroups;
-        use engine::resources::COLLISION_SCALE;
+        use specs::Join;
         use std::mem;
 
         if sys_data.skip.0 {
             return;
         }
 
-        let c_world = &*sys_data.collision_sys;
+        let grid = *sys_data.grid;
+        let dims = [grid.width, grid.height, sys_data.layers.0.len()];
+        if self.grid != grid || self.state_cache.shape() != &dims[..] {
+            self.grid = grid;
+            self.state_cache = Array3::zeros(dims);
+            self.coverage = vec![Vec::new(); grid.width * grid.height];
+            self.footprints.clear();
+        }
+
+        // Cleared at the start of every episode, or stale after the grid changed
+        if sys_data.last_seen.0.len() != grid.width * grid.height {
+            sys_data.last_seen.0 = vec![0.0; grid.width * grid.height];
+        }
+
+        // Forget units that have died or been removed
+        let gone: Vec<Entity> = self.footprints
+            .keys()
+            .filter(|id| !sys_data.ids.is_alive(**id) || sys_data.death.get(**id).is_some())
+            .cloned()
+            .collect();
 
-        let mut c_group = CollisionGroups::new();
-        for i in 0..15 {
-            c_group.modify_membership(i, true);
+        for id in gone {
+            let footprint = self.footprints.remove(&id).unwrap();
+            self.erase(id, &footprint);
         }
 
-        /* This is probably speed uppable using the Dead and Moved marker components */
-        for i in 0..STATE_SIZE {
-            for j in 0..STATE_SIZE {
-                let pt = Point2::new(
-                    ((i * STATE_SCALE) as f64) / COLLISION_SCALE,
-                    ((j * STATE_SCALE) as f64) / COLLISION_SCALE,
-                );
-                let mut intersection = c_world.interferences_with_point(&pt, &c_group);
-
-                if let Some(collider) = intersection.filter(|v| !v.data().detector).next() {
-                    let entity = collider.data().e;
-                    // Need to offset by 1 because the default is 0
-                    self.state_cache[(i, j, 0)] = (entity.id() + 1) as f64;
-                    self.state_cache[(i, j, 1)] = sys_data.hp.get(entity).unwrap().curr_hp as f64;
-
-                    let u_type = sys_data
-                        .unit_types
-                        .typ_ids
-                        .get(&sys_data.tag.get(entity).unwrap().0)
-                        .unwrap();
-                    self.state_cache[(i, j, 2)] = (*u_type + 1) as f64;
-                    self.state_cache[(i, j, 3)] =
-                        (sys_data.faction.get(entity).unwrap().0 + 1) as f64;
-                } else {
-                    for k in 0..4 {
-                        self.state_cache[(i, j, k)] = 0.0;
+        // Position is checked directly rather than relying on `MovedFlag`,
+        // which is cleared every frame even when the state is skipped
+        for (pos, shape, _, id) in (
+            &sys_data.pos,
+            &sys_data.shape,
+            &sys_data.c_handle,
+            &*sys_data.ids,
+        ).join()
+        {
+            if sys_data.death.get(id).is_some() {
+                continue;
+            }
+
+            match self.footprints.get(&id) {
+                Some(footprint) if footprint.pos == *pos && footprint.shape == *shape => continue,
+                _ => {}
+            }
+
+            let mut cells = match self.footprints.remove(&id) {
+                Some(footprint) => {
+                    self.erase(id, &footprint);
+                    footprint.cells
+                }
+                None => Vec::new(),
+            };
+
+            footprint_cells(&grid, pos, shape, &mut cells);
+            for &idx in &cells {
+                self.coverage[idx].push(id);
+            }
+
+            self.footprints.insert(
+                id,
+                Footprint {
+                    pos: *pos,
+                    shape: *shape,
+                    cells,
+                },
+            );
+        }
+
+        let layers = &sys_data.layers.0;
+        let visibility = &*sys_data.visibility;
+        let faction = &sys_data.faction;
+        let seen = |id: Entity| {
+            faction
+                .get(id)
+                .map_or(true, |owner| visibility.observes_unit(id, owner.0))
+        };
+        let enemy = |id: Entity| {
+            faction
+                .get(id)
+                .map_or(false, |owner| !visibility.observers.contains(&owner.0))
+        };
+
+        for (id, footprint) in &self.footprints {
+            let id = *id;
+
+            // Hidden units are written as empty cells
+            self.values_cache.clear();
+            if seen(id) {
+                for layer in layers {
+                    self.values_cache.push(feature_value(layer, id, &sys_data));
+                }
+            }
+
+            for &idx in &footprint.cells {
+                let owner = self.coverage[idx]
+                    .iter()
+                    .cloned()
+                    .filter(|e| seen(*e))
+                    .min_by_key(|e| e.id());
+
+                match owner {
+                    Some(owner) if owner != id => continue,
+                    _ => {}
+                }
+
+                let (i, j) = (idx / grid.height, idx % grid.height);
+                for (k, layer) in layers.iter().enumerate() {
+                    if !layer.is_per_cell() {
+                        let value = self.values_cache.get(k).cloned().unwrap_or(0.0);
+                        self.state_cache[(i, j, k)] = value;
                     }
                 }
             }
         }
 
-        let old_state = mem::replace(&mut sys_data.state.0.features, vec![]);
-        let new_cache = Array3::from_shape_vec([STATE_SIZE, STATE_SIZE, 4], old_state).unwrap();
+        if layers.iter().any(FeatureLayer::is_per_cell) {
+            let tags = &sys_data.tag;
+            let unit_types = &sys_data.unit_types;
+            let last_seen = &mut sys_data.last_seen.0;
+
+            for idx in 0..last_seen.len() {
+                let visible = visibility.observes_cell(idx);
+
+                if visible {
+                    last_seen[idx] = self.coverage[idx]
+                        .iter()
+                        .cloned()
+                        .filter(|e| enemy(*e) && seen(*e))
+                        .min_by_key(|e| e.id())
+                        .and_then(|e| tags.get(e))
+                        .and_then(|tag| unit_types.typ_ids.get(&tag.0))
+                        .map_or(0.0, |u_type| (*u_type + 1) as f64);
+                }
+
+                let (i, j) = (idx / grid.height, idx % grid.height);
+                for (k, layer) in layers.iter().enumerate() {
+                    match *layer {
+                        FeatureLayer::Visible => {
+                            self.state_cache[(i, j, k)] = if visible { 1.0 } else { 0.0 }
+                        }
+                        FeatureLayer::LastSeen => self.state_cache[(i, j, k)] = last_seen[idx],
+                        _ => {}
+                    }
+                }
+            }
+        }
 
-        sys_data.state.0.features = mem::replace(&mut self.state_cache, new_cache).into_raw_vec();
+        {
+            let features = &mut sys_data.state.0.features;
+            features.clear();
+            // Always in standard layout since we created it
+            features.extend_from_slice(self.state_cache.as_slice().unwrap());
+        }
+        sys_data.state.0.feature_array_dims = dims.iter().map(|d| *d as u32).collect();
 
         mem::swap(&mut sys_data.state.0.typed_reward, &mut sys_data.reward.0);
         sys_data.reward.0.clear();
-        sys_data.state.0.terminal = sys_data.terminal.0;
+        // Truncated episodes are over, but didn't end on their own
+        sys_data.state.0.terminal = sys_data.terminal.0 && !sys_data.outcome.truncated;
 
         sys_data.state.0.reward = Some(
             sys_data
@@ -100,3 +269,88 @@ impl<'a> System<'a> for StateBuildSystem {
         );
     }
 }
+
+/// The value of `layer` for the unit `entity`.
+fn feature_value(layer: &FeatureLayer, entity: Entity, sys_data: &StateBuildSystemData) -> f64 {
+    let hp = sys_data.hp.get(entity).unwrap();
+    let tag = &sys_data.tag.get(entity).unwrap().0;
+    let unit_type = &sys_data.unit_types.tag_map[tag];
+
+    let flag = |present: bool| if present { 1.0 } else { 0.0 };
+
+    match *layer {
+        // Need to offset IDs by 1 because the default is 0
+        FeatureLayer::UnitId => (entity.id() + 1) as f64,
+        FeatureLayer::Hp => hp.curr_hp,
+        FeatureLayer::MaxHp => hp.max_hp,
+        FeatureLayer::HpFraction => if hp.max_hp > 0.0 {
+            hp.curr_hp / hp.max_hp
+        } else {
+            0.0
+        },
+        FeatureLayer::UnitType => (sys_data.unit_types.typ_ids[tag] + 1) as f64,
+        FeatureLayer::Faction => (sys_data.faction.get(entity).unwrap().0 + 1) as f64,
+        FeatureLayer::AttackCooldown => match sys_data.attack.get(entity) {
+            Some(atk) if unit_type.attack_delay > 0.0 => {
+                (atk.time_since_last / unit_type.attack_delay).min(1.0)
+            }
+            // Units attack immediately when they acquire a new target
+            _ => 1.0,
+        },
+        FeatureLayer::Speed => sys_data.speed.get(entity).map(|s| s.0).unwrap_or(0.0),
+        FeatureLayer::Movable => flag(sys_data.movable.get(entity).is_some()),
+        FeatureLayer::HasMoveOrder => flag(sys_data.moves.get(entity).is_some()),
+        FeatureLayer::AttackTarget => sys_data
+            .attack
+            .get(entity)
+            .map(|atk| (atk.target.id() + 1) as f64)
+            .unwrap_or(0.0),
+        FeatureLayer::Attribute(ref attr) => {
+            unit_type.attributes.get(attr).cloned().unwrap_or(0.0)
+        }
+        // Filled in per cell by the system itself
+        FeatureLayer::Visible | FeatureLayer::LastSeen => 0.0,
+    }
+}
+
+/// Computes the cells whose sample points lie within the collider for
+/// `shape` at `pos`, storing their indices in `cells`.
+///
+/// The test is done in collision world units so it matches what
+/// the collision world would report bit for bit.
+fn footprint_cells(grid: &ObservationGrid, pos: &Pos, shape: &Shape, cells: &mut Vec<usize>) {
+    use engine::resources::{collider_half_extents, COLLISION_SCALE};
+
+    cells.clear();
+
+    let half = collider_half_extents(shape);
+    let (c_x, c_y) = (pos.x / COLLISION_SCALE, pos.y / COLLISION_SCALE);
+
+    // Candidate cells, padded by one on each side to absorb rounding
+    let range = |center: f64, half: f64, origin: f64, len: usize| {
+        let min = (center - half) * COLLISION_SCALE;
+        let max = (center + half) * COLLISION_SCALE;
+
+        let lo = ((min - origin) / grid.cell_size).floor() as isize - 1;
+        let hi = ((max - origin) / grid.cell_size).ceil() as isize + 1;
+
+        (lo.max(0) as usize, hi.min(len as isize - 1))
+    };
+
+    let (min_i, max_i) = range(c_x, half.x, grid.origin.x, grid.width);
+    let (min_j, max_j) = range(c_y, half.y, grid.origin.y, grid.height);
+
+    for i in min_i as isize..max_i + 1 {
+        for j in min_j as isize..max_j + 1 {
+            let (i, j) = (i as usize, j as usize);
+            let sample = grid.sample_point(i, j);
+
+            let x = sample.x / COLLISION_SCALE - c_x;
+            let y = sample.y / COLLISION_SCALE - c_y;
+
+            if x.abs() <= half.x && y.abs() <= half.y {
+                cells.push(i * grid.height + j);
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use specs::{Entities, Entity, Fetch, FetchMut, ReadStorage, System};
+use engine::components::{Death, FactionId, Hp, Pos, UnitTypeTag};
+use engine::resources::{EpisodeOutcome, FeatureLayers, NeedsKeyInfo, Outcome, Player, RtsState,
+                        Skip, UnitTypeMap, Visibility};
+
+use protos::Unit;
+
+use std::collections::HashMap;
+
+#[derive(SystemData)]
+pub struct StateUpdateSystemData<'a> {
+    pos: ReadStorage<'a, Pos>,
+    hp: ReadStorage<'a, Hp>,
+    faction: ReadStorage<'a, FactionId>,
+    tag: ReadStorage<'a, UnitTypeTag>,
+    death: ReadStorage<'a, Death>,
+    ids: Entities<'a>,
+
+    unit_types: Fetch<'a, UnitTypeMap>,
+    layers: Fetch<'a, FeatureLayers>,
+    players: Fetch<'a, Vec<Player>>,
+    visibility: Fetch<'a, Visibility>,
+    needs_key_info: Fetch<'a, NeedsKeyInfo>,
+    skip: Fetch<'a, Skip>,
+    outcome: Fetch<'a, EpisodeOutcome>,
+
+    state: FetchMut<'a, RtsState>,
+}
+
+/// What the agent was last told about a unit.
+#[derive(Copy, Clone, PartialEq)]
+struct SentUnit {
+    pos: Pos,
+    hp: f64,
+    owner: usize,
+    unit_type: usize,
+}
+
+/// Builds the `StateUpdate` entity list and attaches it to the
+/// state as its `expanded_state`.
+///
+/// A full list (including the unit type constants and a description
+/// of the feature layers) is sent whenever `NeedsKeyInfo` is set,
+/// otherwise only changed fields are sent.
+///
+/// Units none of the agent's factions can see are treated as if they
+/// don't exist, they're deleted when they leave sight and sent in
+/// full on return.
+#[derive(Default)]
+pub struct StateUpdateSystem {
+    sent: HashMap<Entity, SentUnit>,
+}
+
+impl StateUpdateSystem {
+    pub fn new() -> Self {
+        StateUpdateSystem {
+            sent: HashMap::with_capacity(100),
+        }
+    }
+}
+
+impl<'a> System<'a> for StateUpdateSystem {
+    type SystemData = StateUpdateSystemData<'a>;
+
+    fn run(&mut self, mut sys_data: Self::SystemData) {
+        use specs::Join;
+        use prost::Message;
+        use protos::{FeatureLayer as ProtoFeatureLayer, Outcome as ProtoOutcome, StateUpdate,
+                     UnitType as ProtoUnitType};
+
+        if sys_data.skip.0 {
+            return;
+        }
+
+        let mut update = StateUpdate::default();
+
+        if sys_data.needs_key_info.0 {
+            self.sent.clear();
+
+            let unit_types = &*sys_data.unit_types;
+            update.unit_types = unit_types
+                .typ_ids
+                .iter()
+                .filter_map(|(tag, id)| {
+                    unit_types.tag_map.get(tag).map(|unit_type| ProtoUnitType {
+                        unit_type_id: *id as u64,
+                        max_hp: Some(unit_type.max_hp),
+                        movable: Some(if unit_type.movable { 1.0 } else { 0.0 }),
+                        tag: Some(tag.clone()),
+                    })
+                })
+                .collect();
+            update.unit_types.sort_by_key(|unit_type| unit_type.unit_type_id);
+
+            let factions = sys_data.players.len();
+            update.feature_layers = sys_data
+                .layers
+                .0
+                .iter()
+                .map(|layer| ProtoFeatureLayer {
+                    name: layer.name(),
+                    categorical: layer.is_categorical(),
+                    max_value: layer.max_value(unit_types, factions),
+                })
+                .collect();
+        }
+
+        // Catches units removed without us seeing their death,
+        // e.g. during skipped frames. These go first, since a new unit
+        // may already have reused the removed unit's ID
+        {
+            let ids = &sys_data.ids;
+            let units = &mut update.units;
+            self.sent.retain(|id, _| {
+                let alive = ids.is_alive(*id);
+                if !alive {
+                    units.push(deleted_unit(*id));
+                }
+                alive
+            });
+        }
+
+        for (pos, hp, faction, tag, id) in (
+            &sys_data.pos,
+            &sys_data.hp,
+            &sys_data.faction,
+            &sys_data.tag,
+            &*sys_data.ids,
+        ).join()
+        {
+            let hidden = !sys_data.visibility.observes_unit(id, faction.0);
+
+            if sys_data.death.get(id).is_some() || hidden {
+                // Units that die before ever being sent are never mentioned
+                if self.sent.remove(&id).is_some() {
+                    update.units.push(deleted_unit(id));
+                }
+                continue;
+            }
+
+            let current = SentUnit {
+                pos: *pos,
+                hp: hp.curr_hp,
+                owner: faction.0,
+                unit_type: sys_data.unit_types.typ_ids.get(&tag.0).cloned().unwrap_or(0),
+            };
+
+            let unit = match self.sent.get(&id) {
+                Some(prev) if *prev == current => continue,
+                prev => delta_unit(id, prev, &current),
+            };
+
+            self.sent.insert(id, current);
+            update.units.push(unit);
+        }
+
+        let truncated = sys_data.outcome.truncated;
+        update.outcome = sys_data.outcome.outcome.map(|outcome| ProtoOutcome {
+            winner: match outcome {
+                Outcome::Victory(winner) => Some(winner as u32),
+                Outcome::Draw => None,
+            },
+            truncated: Some(truncated),
+        });
+
+        let mut buf = Vec::new();
+        // Encoding into a Vec can't run out of space
+        update
+            .encode(&mut buf)
+            .expect("Could not encode StateUpdate");
+
+        sys_data.state.0.expanded_state = Some(buf);
+    }
+}
+
+fn deleted_unit(id: Entity) -> Unit {
+    Unit {
+        id: id.id(),
+        delete: true,
+        ..Default::default()
+    }
+}
+
+/// Builds a unit containing only the fields that differ from `prev`,
+/// or every field if it has never been sent.
+fn delta_unit(id: Entity, prev: Option<&SentUnit>, current: &SentUnit) -> Unit {
+    use protos::DeltaPos;
+
+    let x = match prev {
+        Some(prev) if prev.pos.x == current.pos.x => None,
+        _ => Some(current.pos.x),
+    };
+
+    let y = match prev {
+        Some(prev) if prev.pos.y == current.pos.y => None,
+        _ => Some(current.pos.y),
+    };
+
+    Unit {
+        id: id.id(),
+        owner_id: match prev {
+            Some(prev) if prev.owner == current.owner => None,
+            _ => Some(current.owner as u64),
+        },
+        unit_type_id: match prev {
+            Some(prev) if prev.unit_type == current.unit_type => None,
+            _ => Some(current.unit_type as u64),
+        },
+        pos: if x.is_some() || y.is_some() {
+            Some(DeltaPos { x, y })
+        } else {
+            None
+        },
+        hp: match prev {
+            Some(prev) if prev.hp == current.hp => None,
+            _ => Some(current.hp),
+        },
+        delete: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StateUpdateSystem;
+    use engine::test_util::{inline_rts, state_update, unit};
+
+    #[test]
+    fn reused_ids_are_deleted_before_they_are_resent() {
+        use shred::RunNow;
+        use engine::components::{FactionId, Hp, Pos, UnitTypeTag};
+
+        let mut rts = inline_rts(
+            "",
+            r#"
+            { unit_type = "soldier", faction = 0, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "tower", faction = 1, pos = { x = 300.0, y = 300.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+        let mut sys = StateUpdateSystem::new();
+        sys.run_now(&rts.world.res);
+
+        // The tower is replaced without the system seeing it die,
+        // so its replacement gets the same index
+        let tower = unit(&rts, 1);
+        rts.world.delete_entity(tower).unwrap();
+        rts.world.maintain();
+        let replacement = rts.world
+            .create_entity()
+            .with(Pos::new(200.0, 200.0))
+            .with(Hp {
+                max_hp: 100.0,
+                curr_hp: 100.0,
+            })
+            .with(FactionId(1))
+            .with(UnitTypeTag("tower".to_string()))
+            .build();
+        assert_eq!(replacement.id(), tower.id());
+
+        sys.run_now(&rts.world.res);
+
+        let sent: Vec<_> = state_update(&rts)
+            .units
+            .iter()
+            .filter(|unit| unit.id == tower.id())
+            .map(|unit| unit.delete)
+            .collect();
+        assert_eq!(sent, vec![true, false]);
+    }
+}
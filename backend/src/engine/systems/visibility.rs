@@ -0,0 +1,161 @@
+use specs::{Entities, Fetch, FetchMut, ReadStorage, System};
+use engine::components::{Death, FactionId, Pos, UnitTypeTag};
+use engine::resources::{ObservationGrid, Player, Skip, UnitTypeMap, Visibility};
+
+use std::collections::HashSet;
+
+#[derive(SystemData)]
+pub struct VisibilitySystemData<'a> {
+    pos: ReadStorage<'a, Pos>,
+    faction: ReadStorage<'a, FactionId>,
+    tag: ReadStorage<'a, UnitTypeTag>,
+    death: ReadStorage<'a, Death>,
+    ids: Entities<'a>,
+
+    unit_types: Fetch<'a, UnitTypeMap>,
+    players: Fetch<'a, Vec<Player>>,
+    grid: Fetch<'a, ObservationGrid>,
+    skip: Fetch<'a, Skip>,
+
+    visibility: FetchMut<'a, Visibility>,
+}
+
+/// Computes which cells and enemy units each faction can see
+/// when fog of war is enabled.
+///
+/// A unit sees everything within its unit type's `sight_range`
+/// of its position, dying units see nothing.
+#[derive(Default)]
+pub struct VisibilitySystem {
+    // (faction, position, sight range)
+    viewer_cache: Vec<(usize, Pos, f64)>,
+}
+
+impl VisibilitySystem {
+    pub fn new() -> Self {
+        VisibilitySystem {
+            viewer_cache: Vec::with_capacity(100),
+        }
+    }
+}
+
+impl<'a> System<'a> for VisibilitySystem {
+    type SystemData = VisibilitySystemData<'a>;
+
+    fn run(&mut self, mut sys_data: Self::SystemData) {
+        use specs::Join;
+
+        if sys_data.skip.0 || !sys_data.visibility.fog_of_war {
+            return;
+        }
+
+        let factions = sys_data.players.len();
+        let grid = *sys_data.grid;
+        let viewers = &mut self.viewer_cache;
+        let visibility = &mut *sys_data.visibility;
+
+        visibility.cells.resize(factions, Vec::new());
+        for cells in &mut visibility.cells {
+            cells.clear();
+            cells.resize(grid.width * grid.height, false);
+        }
+
+        visibility.units.resize(factions, HashSet::new());
+        for units in &mut visibility.units {
+            units.clear();
+        }
+
+        for (pos, faction, tag, id) in (
+            &sys_data.pos,
+            &sys_data.faction,
+            &sys_data.tag,
+            &*sys_data.ids,
+        ).join()
+        {
+            if sys_data.death.get(id).is_some() || faction.0 >= factions {
+                continue;
+            }
+
+            let range = sys_data
+                .unit_types
+                .tag_map
+                .get(&tag.0)
+                .map_or(0.0, |u_type| u_type.sight_range);
+
+            viewers.push((faction.0, *pos, range));
+        }
+
+        for &(faction, ref pos, range) in viewers.iter() {
+            mark_cells(&grid, pos, range, &mut visibility.cells[faction]);
+        }
+
+        for (pos, faction, id) in (&sys_data.pos, &sys_data.faction, &*sys_data.ids).join() {
+            for &(viewer, ref viewer_pos, range) in viewers.iter() {
+                if viewer != faction.0 && (**pos - **viewer_pos).norm() <= range {
+                    visibility.units[viewer].insert(id);
+                }
+            }
+        }
+
+        viewers.clear();
+    }
+}
+
+/// Marks every cell whose sample point is within `range` of `pos` as visible.
+fn mark_cells(grid: &ObservationGrid, pos: &Pos, range: f64, cells: &mut [bool]) {
+    let span = |center: f64, origin: f64, len: usize| {
+        let lo = ((center - range - origin) / grid.cell_size).floor() as isize;
+        let hi = ((center + range - origin) / grid.cell_size).ceil() as isize;
+
+        (lo.max(0), hi.min(len as isize - 1))
+    };
+
+    let (min_i, max_i) = span(pos.x, grid.origin.x, grid.width);
+    let (min_j, max_j) = span(pos.y, grid.origin.y, grid.height);
+
+    for i in min_i..max_i + 1 {
+        for j in min_j..max_j + 1 {
+            let (i, j) = (i as usize, j as usize);
+            if (*grid.sample_point(i, j) - **pos).norm() <= range {
+                cells[i * grid.height + j] = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use engine::test_util::{inline_rts, state_update};
+
+    #[test]
+    fn fog_of_war_hides_units_from_the_agent() {
+        use engine::resources::RtsState;
+
+        // The agent plays faction 1, which can only see the nearby soldier
+        let mut rts = inline_rts(
+            "agent_factions = { 1 }, fog_of_war = true,",
+            r#"
+            { unit_type = "soldier", faction = 1, pos = { x = 100.0, y = 100.0 } },
+            { unit_type = "soldier", faction = 0, pos = { x = 400.0, y = 400.0 } },
+            { unit_type = "soldier", faction = 0, pos = { x = 150.0, y = 100.0 } },
+            "#,
+            "",
+        );
+
+        rts.reset().unwrap();
+
+        {
+            let state = rts.world.read_resource::<RtsState>();
+
+            // Unit IDs are offset by 1 in the first layer
+            let ids: Vec<_> = state.0.features.chunks(4).map(|cell| cell[0]).collect();
+            assert!(ids.contains(&1.0));
+            assert!(!ids.contains(&2.0));
+            assert!(ids.contains(&3.0));
+        }
+
+        let mut sent: Vec<_> = state_update(&rts).units.iter().map(|unit| unit.id).collect();
+        sent.sort();
+        assert_eq!(sent, vec![0, 2]);
+    }
+}
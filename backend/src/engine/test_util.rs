@@ -0,0 +1,136 @@
+//! Scenarios and actions shared by the engine's tests.
+
+use super::Rts;
+use protos::{ActionList, StateUpdate, UnitAction};
+use scaii_defs::protos::{Action, MultiMessage};
+use scaii_defs::protos::scaii_packet::SpecificMsg;
+use specs::Entity;
+use std::path::PathBuf;
+
+/// Builds an `Rts` for an inline scenario with a mobile `soldier` and a
+/// static `tower` unit type, given the extra `sky_init` fields, the
+/// units `sky_reset` places and any hooks.
+///
+/// Units are given IDs in the order they're listed.
+pub fn inline_rts(init: &str, units: &str, hooks: &str) -> Rts<'static, 'static> {
+    let mut rts = Rts::new();
+    rts.lua_source = Some(format!(
+        r#"
+        function sky_init()
+            return {{
+                factions = 2,
+                unit_types = {{
+                    {{
+                        tag = "soldier",
+                        max_hp = 100,
+                        shape = {{ body = "triangle", base_len = 10.0 }},
+                        speed = 40.0,
+                        attack_range = 50.0,
+                        attack_dmg = 10,
+                        attack_delay = 1.0,
+                    }},
+                    {{
+                        tag = "tower",
+                        max_hp = 100,
+                        shape = {{ body = "rect", width = 20.0, height = 20.0 }},
+                        can_move = false,
+                        attack_range = 1.0,
+                        attack_dmg = 1,
+                        attack_delay = 1.0,
+                        kill_reward = 50,
+                    }},
+                }},
+                {}
+            }}
+        end
+
+        function sky_reset(rng)
+            return {{ {} }}
+        end
+
+        {}
+        "#,
+        init, units, hooks
+    ));
+
+    rts
+}
+
+/// The directory holding the bundled scenarios.
+pub fn scenario_dir() -> String {
+    format!("{}/lua", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Builds an `Rts` for the bundled scenario `name`, e.g. `"tower_example"`.
+pub fn scenario_rts(name: &str) -> Rts<'static, 'static> {
+    let mut rts = Rts::new();
+    rts.lua_path = Some(PathBuf::from(format!("{}/{}.lua", scenario_dir(), name)));
+
+    rts
+}
+
+/// An agent action carrying `list`.
+pub fn action(list: ActionList) -> Action {
+    use prost::Message;
+
+    let mut buf = Vec::new();
+    list.encode(&mut buf).unwrap();
+
+    Action {
+        alternate_actions: Some(buf),
+        ..Default::default()
+    }
+}
+
+/// An agent action giving the listed orders.
+pub fn orders(actions: Vec<UnitAction>) -> Action {
+    action(ActionList {
+        actions,
+        ..Default::default()
+    })
+}
+
+pub fn move_to(unit_id: u64, x: f64, y: f64) -> UnitAction {
+    use protos::{MoveTo, Pos};
+    use protos::unit_action::Action as RtsAction;
+
+    UnitAction {
+        unit_id,
+        action: Some(RtsAction::MoveTo(MoveTo { pos: Pos { x, y } })),
+    }
+}
+
+pub fn attack(unit_id: u64, target_id: u32) -> UnitAction {
+    use protos::AttackUnit;
+    use protos::unit_action::Action as RtsAction;
+
+    UnitAction {
+        unit_id,
+        action: Some(RtsAction::AttackUnit(AttackUnit { target_id })),
+    }
+}
+
+/// The entity for the unit with ID `id`.
+pub fn unit(rts: &Rts, id: u32) -> Entity {
+    rts.world.entities().entity(id)
+}
+
+/// The `StateUpdate` attached to the last state that was built.
+pub fn state_update(rts: &Rts) -> StateUpdate {
+    use prost::Message;
+    use engine::resources::RtsState;
+
+    let state = rts.world.read_resource::<RtsState>();
+    StateUpdate::decode(&state.0.expanded_state.as_ref().unwrap()[..]).unwrap()
+}
+
+/// The number of error packets in `mm`.
+pub fn error_count(mm: &MultiMessage) -> usize {
+    mm.packets
+        .iter()
+        .filter(|packet| match packet.specific_msg {
+            Some(SpecificMsg::Err(_)) => true,
+            _ => false,
+        })
+        .count()
+}
@@ -0,0 +1,363 @@
+use rayon::ThreadPool;
+
+use scaii_defs::protos::{Action, State};
+
+use engine::Rts;
+use engine::resources::{EpisodeOutcome, RtsState};
+use error::SkyError;
+use protos::Config;
+
+/// An `Rts` that can be handed to the pool's threads.
+struct Env(Rts<'static, 'static>);
+
+// `Rts` isn't `Send` only because of what its `LuaSystem` owns:
+//
+// * The `rlua::Lua` state, which isn't tied to the thread that created it,
+//   it just mustn't be used from two threads at once.
+// * The `Rc<WorldView>` stored in the `__sky_world` global. Every clone of
+//   it lives inside that same Lua state (or on the stack during `run_hooks`),
+//   so no clone is ever shared with another env.
+// * The `__sky_rts_rng` pointer and the `WorldView`'s collision world
+//   pointer, which point at resources boxed inside this env's own `World`,
+//   so they stay valid when the env moves.
+//
+// Neither dispatcher has thread-local systems. An env is only reachable
+// through `VecRts`, and `par_iter_mut` hands each one to a single thread
+// at a time, so everything above is moved wholesale and never shared.
+unsafe impl Send for Env {}
+
+/// Several independent RTS worlds stepped together on a thread pool,
+/// for agents that train on batches of environments.
+///
+/// After every `reset` or `step` the results are laid out contiguously,
+/// environment by environment. Environments whose episodes end or are
+/// truncated are reset immediately, so the observation for such an
+/// environment is the first one of its next episode, and the last one of
+/// the old episode is kept in `final_states`.
+///
+/// Every environment is always stepped, an error in one doesn't stop the
+/// others. Errors are collected into `errors` instead, an environment
+/// that hits a fatal error is left as it was.
+pub struct VecRts {
+    envs: Vec<Env>,
+    pool: ThreadPool,
+
+    /// Every environment's features, each `observation_len` long.
+    pub observations: Vec<f64>,
+    /// The reward each environment got for its last action.
+    pub rewards: Vec<f64>,
+    /// Whether each environment's episode ended on its last action.
+    pub terminals: Vec<bool>,
+    /// Whether each environment's episode was cut off by its time limit.
+    pub truncated: Vec<bool>,
+    /// The state each environment ended its episode in on its last
+    /// action, `None` unless it was just reset.
+    pub final_states: Vec<Option<State>>,
+    /// Errors raised during the last `reset` or `step`,
+    /// along with the environment that raised them.
+    pub errors: Vec<(usize, SkyError)>,
+}
+
+impl VecRts {
+    /// Creates `n` environments from the same `Config`, stepped on
+    /// `threads` threads.
+    ///
+    /// If the `Config` has a seed, the environments are each seeded with
+    /// it followed by their index, so they don't play the same episodes.
+    /// Replays are recorded into an `env_<i>` directory for each one.
+    pub fn new(cfg: &Config, n: usize, threads: usize) -> Result<Self, SkyError> {
+        use rayon::Configuration;
+
+        let pool = ThreadPool::new(Configuration::new().num_threads(threads))
+            .map_err(|e| SkyError::Config(format!("Could not start thread pool: {}", e)))?;
+
+        let mut envs = Vec::with_capacity(n);
+        for i in 0..n {
+            let mut rts = Rts::new();
+            rts.configure(cfg)?;
+
+            if let Some(ref seed) = cfg.random_seed {
+                let mut seed = seed.seed.clone();
+                seed.push(i as u64);
+                rts.seed(&seed);
+            }
+
+            if let Some(ref mut recorder) = rts.recorder {
+                recorder.dir.push(format!("env_{}", i));
+            }
+
+            envs.push(Env(rts));
+        }
+
+        Ok(VecRts {
+            envs,
+            pool,
+            observations: vec![],
+            rewards: vec![0.0; n],
+            terminals: vec![false; n],
+            truncated: vec![false; n],
+            final_states: vec![None; n],
+            errors: vec![],
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envs.is_empty()
+    }
+
+    /// The length of a single environment's observation.
+    pub fn observation_len(&self) -> usize {
+        self.envs.first().map_or(0, |env| {
+            env.0.world.read_resource::<RtsState>().0.features.len()
+        })
+    }
+
+    /// The shape of `observations`, the number of environments
+    /// followed by each one's feature array dimensions.
+    pub fn observation_dims(&self) -> Vec<u32> {
+        let mut dims = vec![self.envs.len() as u32];
+        if let Some(env) = self.envs.first() {
+            let state = env.0.world.read_resource::<RtsState>();
+            dims.extend(state.0.feature_array_dims.iter().cloned());
+        }
+
+        dims
+    }
+
+    /// Starts a new episode in every environment.
+    ///
+    /// Errors from individual environments end up in `errors`.
+    pub fn reset(&mut self) -> Result<(), SkyError> {
+        use rayon::prelude::*;
+
+        let results: Vec<_> = {
+            let envs = &mut self.envs;
+            self.pool
+                .install(|| envs.par_iter_mut().map(|env| env.reset()).collect())
+        };
+
+        self.errors.clear();
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(errors) => self.errors.extend(errors.into_iter().map(|e| (i, e))),
+                Err(e) => self.errors.push((i, e)),
+            }
+            self.rewards[i] = 0.0;
+            self.terminals[i] = false;
+            self.truncated[i] = false;
+            self.final_states[i] = None;
+        }
+
+        self.gather_observations();
+
+        Ok(())
+    }
+
+    /// Gives every environment its action, in order, and steps them all.
+    ///
+    /// Each action is carried out like a single `Action` sent to the
+    /// backend, including any repeated or skipped frames it asks for.
+    /// Errors from individual environments end up in `errors`, this only
+    /// fails if there isn't exactly one action per environment.
+    pub fn step(&mut self, actions: Vec<Action>) -> Result<(), SkyError> {
+        use rayon::prelude::*;
+
+        if actions.len() != self.envs.len() {
+            return Err(SkyError::Config(format!(
+                "Got {} actions for {} environments",
+                actions.len(),
+                self.envs.len()
+            )));
+        }
+
+        let results: Vec<_> = {
+            let envs = &mut self.envs;
+            self.pool.install(|| {
+                envs.par_iter_mut()
+                    .zip(actions.into_par_iter())
+                    .map(|(env, action)| env.step(action))
+                    .collect()
+            })
+        };
+
+        self.errors.clear();
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(step) => {
+                    self.errors.extend(step.errors.into_iter().map(|e| (i, e)));
+                    self.rewards[i] = step.reward;
+                    self.terminals[i] = step.terminal;
+                    self.truncated[i] = step.truncated;
+                    self.final_states[i] = step.final_state;
+                }
+                Err(e) => {
+                    self.errors.push((i, e));
+                    self.rewards[i] = 0.0;
+                    self.terminals[i] = false;
+                    self.truncated[i] = false;
+                    self.final_states[i] = None;
+                }
+            }
+        }
+
+        self.gather_observations();
+
+        Ok(())
+    }
+
+    fn gather_observations(&mut self) {
+        self.observations.clear();
+        for env in &self.envs {
+            let state = env.0.world.read_resource::<RtsState>();
+            self.observations.extend_from_slice(&state.0.features);
+        }
+    }
+}
+
+/// What happened to a single environment during a step.
+struct EnvStep {
+    reward: f64,
+    terminal: bool,
+    truncated: bool,
+    /// The state the episode ended in, if the env was reset
+    final_state: Option<State>,
+    errors: Vec<SkyError>,
+}
+
+impl Env {
+    /// Returns the non-fatal errors raised while resetting.
+    fn reset(&mut self) -> Result<Vec<SkyError>, SkyError> {
+        self.0.restart()?;
+        self.drain_errors()
+    }
+
+    fn step(&mut self, action: Action) -> Result<EnvStep, SkyError> {
+        self.0.step(action);
+
+        let mut errors = self.drain_errors()?;
+
+        let (reward, terminal) = {
+            let state = self.0.world.read_resource::<RtsState>();
+            (state.0.reward.unwrap_or_default(), state.0.terminal)
+        };
+        let truncated = self.0.world.read_resource::<EpisodeOutcome>().truncated;
+
+        let final_state = if terminal || truncated {
+            let state = self.0.world.read_resource::<RtsState>().0.clone();
+            errors.extend(self.reset()?);
+            Some(state)
+        } else {
+            None
+        };
+
+        Ok(EnvStep {
+            reward,
+            terminal,
+            truncated,
+            final_state,
+            errors,
+        })
+    }
+
+    /// Takes the errors out of the world, failing on the first fatal one.
+    fn drain_errors(&mut self) -> Result<Vec<SkyError>, SkyError> {
+        use engine::resources::Errors;
+
+        let mut errors: Vec<_> = self.0.world.write_resource::<Errors>().0.drain(..).collect();
+        if let Some(pos) = errors.iter().position(|e| e.is_fatal()) {
+            return Err(errors.swap_remove(pos));
+        }
+
+        Ok(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VecRts;
+    use engine::test_util::scenario_dir;
+    use protos::{Config, Scenario};
+    use scaii_defs::protos::Action;
+
+    /// A config for the tower example scenario.
+    fn tower_config() -> Config {
+        Config {
+            scenario: Some(Scenario {
+                path: "tower_example".to_string(),
+                lua_source: None,
+            }),
+            scenario_search_path: vec![scenario_dir()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn vec_rts_batches_envs() {
+        use protos::Seed;
+
+        let cfg = Config {
+            random_seed: Some(Seed { seed: vec![7] }),
+            ..tower_config()
+        };
+
+        let mut envs = VecRts::new(&cfg, 3, 2).unwrap();
+        envs.reset().unwrap();
+
+        let dims = envs.observation_dims();
+        assert_eq!(dims[0], 3);
+        assert_eq!(
+            envs.observations.len(),
+            dims.iter().map(|d| *d as usize).product::<usize>()
+        );
+        assert_eq!(envs.observations.len(), 3 * envs.observation_len());
+
+        envs.step(vec![Action::default(); 3]).unwrap();
+        assert_eq!(envs.rewards.len(), 3);
+        assert_eq!(envs.observations.len(), 3 * envs.observation_len());
+
+        assert!(envs.step(vec![Action::default(); 2]).is_err());
+    }
+
+    #[test]
+    fn vec_rts_reports_each_env() {
+        use error::SkyError;
+
+        let cfg = Config {
+            max_steps: Some(2),
+            ..tower_config()
+        };
+
+        let mut envs = VecRts::new(&cfg, 3, 2).unwrap();
+        envs.reset().unwrap();
+        assert!(envs.errors.is_empty());
+
+        // A malformed action only affects its own env, and every env
+        // is still stepped into its time limit
+        let garbage = Action {
+            alternate_actions: Some(vec![0xff; 4]),
+            ..Default::default()
+        };
+        envs.step(vec![Action::default(), garbage, Action::default()]).unwrap();
+
+        assert_eq!(envs.errors.len(), 1);
+        match envs.errors[0] {
+            (1, SkyError::Decode(_)) => {}
+            ref e => panic!("Unexpected error {:?}", e),
+        }
+
+        assert_eq!(envs.truncated, vec![true; 3]);
+        for state in &envs.final_states {
+            assert!(!state.as_ref().unwrap().terminal);
+        }
+
+        // The envs were reset, so this step doesn't end any episodes
+        envs.step(vec![Action::default(); 3]).unwrap();
+        assert!(envs.errors.is_empty());
+        assert_eq!(envs.truncated, vec![false; 3]);
+        assert!(envs.final_states.iter().all(|state| state.is_none()));
+    }
+}
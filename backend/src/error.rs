@@ -0,0 +1,103 @@
+use prost::DecodeError;
+use rlua::Error as LuaError;
+use scaii_defs::protos::Error as ScaiiError;
+
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// Errors that can occur while configuring or running the RTS.
+///
+/// These are reported back as `scaii_defs::protos::Error` packets
+/// rather than panicking, and carry whether the backend can continue
+/// afterwards.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SkyError {
+    /// A Lua script failed to load, or a scenario function errored.
+    Lua {
+        context: String,
+        description: String,
+        fatal: bool,
+    },
+    /// A protobuf message sent to the RTS could not be decoded.
+    Decode(String),
+    /// The configuration or scenario is invalid.
+    Config(String),
+    /// An order was issued that cannot be carried out,
+    /// such as ordering a dead unit.
+    InvalidOrder(String),
+    /// A replay could not be recorded or played back.
+    Replay(String),
+}
+
+impl SkyError {
+    pub fn lua<S: Into<String>>(context: S, err: LuaError, fatal: bool) -> Self {
+        SkyError::Lua {
+            context: context.into(),
+            description: format!("{}", err),
+            fatal,
+        }
+    }
+
+    /// Whether the backend is unusable until it's reconfigured.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            SkyError::Lua { fatal, .. } => fatal,
+            SkyError::Config(_) => true,
+            SkyError::Decode(_) | SkyError::InvalidOrder(_) | SkyError::Replay(_) => false,
+        }
+    }
+
+    pub fn to_scaii_error(&self) -> ScaiiError {
+        ScaiiError {
+            fatal: Some(self.is_fatal()),
+            error_info: None,
+            description: format!("{}", self),
+        }
+    }
+}
+
+impl Display for SkyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SkyError::Lua {
+                ref context,
+                ref description,
+                ..
+            } => write!(f, "Lua error while {}:\n\t{}", context, description),
+            SkyError::Decode(ref description) => {
+                write!(f, "Could not decode message:\n\t{}", description)
+            }
+            SkyError::Config(ref description) => {
+                write!(f, "Invalid configuration:\n\t{}", description)
+            }
+            SkyError::InvalidOrder(ref description) => {
+                write!(f, "Invalid order:\n\t{}", description)
+            }
+            SkyError::Replay(ref description) => write!(f, "Replay error:\n\t{}", description),
+        }
+    }
+}
+
+impl Error for SkyError {
+    fn description(&self) -> &str {
+        match *self {
+            SkyError::Lua { .. } => "Lua error",
+            SkyError::Decode(_) => "Protobuf decoding error",
+            SkyError::Config(_) => "Configuration error",
+            SkyError::InvalidOrder(_) => "Invalid order",
+            SkyError::Replay(_) => "Replay error",
+        }
+    }
+}
+
+impl From<LuaError> for SkyError {
+    fn from(err: LuaError) -> Self {
+        SkyError::lua("loading the scenario", err, true)
+    }
+}
+
+impl From<DecodeError> for SkyError {
+    fn from(err: DecodeError) -> Self {
+        SkyError::Decode(format!("{}", err))
+    }
+}
@@ -9,6 +9,7 @@ extern crate prost;
 #[macro_use]
 extern crate prost_derive;
 extern crate rand;
+extern crate rayon;
 extern crate rlua;
 extern crate scaii_defs;
 extern crate serde;
@@ -27,6 +28,7 @@ pub mod protos;
 pub mod error;
 
 use engine::Rts;
+use error::SkyError;
 
 use scaii_defs::{Backend, BackendSupported, Module, SerializationStyle};
 use scaii_defs::protos::{BackendCfg, MultiMessage, ScaiiPacket};
@@ -34,7 +36,7 @@ use scaii_defs::protos::{BackendCfg, MultiMessage, ScaiiPacket};
 use std::error::Error;
 
 const SUPPORTED: BackendSupported = BackendSupported {
-    serialization: SerializationStyle::None,
+    serialization: SerializationStyle::Full,
 };
 
 pub struct Context<'a, 'b> {
@@ -47,29 +49,16 @@ impl<'a, 'b> Context<'a, 'b> {
         self.rts.diverge();
     }
 
-    fn configure(&mut self, cfg: &BackendCfg) -> Result<(), Box<Error>> {
-        use protos::{Config, Scenario};
+    fn configure(&mut self, cfg: &BackendCfg) -> Result<(), SkyError> {
+        use protos::Config;
         use prost::Message;
-        use std::env;
-
-        use std::path::PathBuf;
 
         if let Some(ref bytes) = cfg.cfg_msg {
-            let cfg = Config::decode(&*bytes)?;
-
-            self.rts.render = cfg.emit_viz.unwrap_or_default();
-
-            match cfg.scenario {
-                Some(Scenario { ref path }) => {
-                    self.rts.lua_path = Some(PathBuf::from(format!(
-                        "{}/.scaii/backends/sky-rts/maps/{}.lua",
-                        env::var("HOME")?,
-                        path
-                    )));
-
-                    Ok(())
-                }
-                _ => Err(From::from("Unsupported config!")),
+            let rts_cfg = Config::decode(&*bytes)?;
+            if cfg.is_replay_mode {
+                self.rts.play_replay(&rts_cfg)
+            } else {
+                self.rts.configure(&rts_cfg)
             }
         } else {
             Ok(())
@@ -88,25 +77,23 @@ impl<'a, 'b> Module for Context<'a, 'b> {
             Some(SpecificMsg::Config(Cfg {
                 which_module: Some(WhichModule::BackendCfg(ref backend_cfg)),
             })) => {
-                let out = self.configure(backend_cfg);
-                self.awaiting_msgs.push(util::ack_msg());
-                out
+                let mm = match self.configure(backend_cfg) {
+                    Ok(()) => util::ack_msg(),
+                    Err(e) => util::error_msg(&e),
+                };
+                self.awaiting_msgs.push(mm);
+                Ok(())
             }
             Some(SpecificMsg::ResetEnv(true)) => {
-                let mm = self.rts.reset();
+                let mm = match self.rts.reset() {
+                    Ok(mm) => mm,
+                    Err(e) => util::error_msg(&e),
+                };
                 self.awaiting_msgs.push(mm);
                 Ok(())
             }
             Some(SpecificMsg::Action(ref action)) => {
-                self.rts.action_input(action.clone());
-                let mut mm = self.rts.update();
-
-                self.rts.action_input(Default::default());
-
-                while self.rts.skip() {
-                    mm = self.rts.update();
-                }
-
+                let mm = self.rts.act(action.clone());
                 self.awaiting_msgs.push(mm);
                 Ok(())
             }
@@ -128,21 +115,26 @@ impl<'a, 'b> Backend for Context<'a, 'b> {
         SUPPORTED
     }
 
-    fn serialize(&mut self, _into: Option<Vec<u8>>) -> Result<Vec<u8>, Box<Error>> {
-        unimplemented!()
+    fn serialize(&mut self, into: Option<Vec<u8>>) -> Result<Vec<u8>, Box<Error>> {
+        self.rts.serialize(into)
     }
 
-    fn deserialize(&mut self, _buf: &[u8]) -> Result<(), Box<Error>> {
-        unimplemented!()
+    fn deserialize(&mut self, buf: &[u8]) -> Result<(), Box<Error>> {
+        self.rts.deserialize(buf)
     }
 
-    fn serialize_diverging(&mut self, _into: Option<Vec<u8>>) -> Result<Vec<u8>, Box<Error>> {
-        unimplemented!()
+    /// The snapshot itself is identical to a non-diverging one,
+    /// divergence is applied on load.
+    fn serialize_diverging(&mut self, into: Option<Vec<u8>>) -> Result<Vec<u8>, Box<Error>> {
+        self.rts.serialize(into)
     }
 
-    fn deserialize_diverging(&mut self, _buf: &[u8]) -> Result<(), Box<Error>> {
+    /// Loads the state and then reseeds the RNG so that
+    /// replaying the same actions yields a different stochastic future.
+    fn deserialize_diverging(&mut self, buf: &[u8]) -> Result<(), Box<Error>> {
+        self.rts.deserialize(buf)?;
         self.diverge();
-        unimplemented!()
+        Ok(())
     }
 }
 
@@ -1,18 +1,45 @@
+mod scenario_path;
+
+pub use self::scenario_path::*;
+
 const SEED_SIZE: usize = 256;
 
 use rand::{Isaac64Rng, Rng, SeedableRng};
 use rand;
 
-use scaii_defs::protos::MultiMessage;
+use scaii_defs::protos::{MultiMessage, ScaiiPacket};
+
+use error::SkyError;
 
 pub fn make_rng() -> Isaac64Rng {
     Isaac64Rng::from_seed(&seed()[..])
 }
 
+pub fn make_seeded_rng(seed: &[u64]) -> Isaac64Rng {
+    Isaac64Rng::from_seed(seed)
+}
+
 pub fn diverge(rng: &mut Isaac64Rng) {
     rng.reseed(&seed()[..]);
 }
 
+/// Reseeds `rng` deterministically from the output of `source`.
+pub fn reseed_from<R: Rng>(rng: &mut Isaac64Rng, source: &mut R) {
+    let mut buf = [0; SEED_SIZE];
+    for v in buf.iter_mut() {
+        *v = source.gen();
+    }
+    rng.reseed(&buf[..]);
+}
+
+/// A 64-bit FNV-1a hash, which unlike the standard library's
+/// hashers is guaranteed to be stable across builds.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
 fn seed() -> [u64; SEED_SIZE] {
     let mut buf = [0; SEED_SIZE];
     for i in 0..SEED_SIZE {
@@ -22,7 +49,7 @@ fn seed() -> [u64; SEED_SIZE] {
 }
 
 pub fn ack_msg() -> MultiMessage {
-    use scaii_defs::protos::{Ack, ScaiiPacket};
+    use scaii_defs::protos::Ack;
     use scaii_defs::protos;
 
     let scaii_packet = ScaiiPacket {
@@ -41,3 +68,27 @@ pub fn ack_msg() -> MultiMessage {
         packets: vec![scaii_packet],
     }
 }
+
+pub fn error_packet(err: &SkyError) -> ScaiiPacket {
+    use scaii_defs::protos;
+
+    ScaiiPacket {
+        src: protos::Endpoint {
+            endpoint: Some(protos::endpoint::Endpoint::Backend(
+                protos::BackendEndpoint {},
+            )),
+        },
+        dest: protos::Endpoint {
+            endpoint: Some(protos::endpoint::Endpoint::Core(protos::CoreEndpoint {})),
+        },
+        specific_msg: Some(protos::scaii_packet::SpecificMsg::Err(
+            err.to_scaii_error(),
+        )),
+    }
+}
+
+pub fn error_msg(err: &SkyError) -> MultiMessage {
+    MultiMessage {
+        packets: vec![error_packet(err)],
+    }
+}
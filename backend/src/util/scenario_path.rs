@@ -0,0 +1,89 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use error::SkyError;
+
+/// Environment variable holding extra scenario directories,
+/// separated the same way as `PATH` on the current platform.
+pub const SCENARIO_PATH_VAR: &str = "SKY_RTS_SCENARIO_PATH";
+
+/// Finds the Lua file for the scenario `name`.
+///
+/// Absolute paths and paths relative to the working directory are
+/// used directly. Otherwise the directories in `search_path` are tried,
+/// followed by those in `SKY_RTS_SCENARIO_PATH` and finally
+/// `$HOME/.scaii/backends/sky-rts/maps` (if `HOME` is set).
+/// In every case the `.lua` extension may be omitted.
+pub fn resolve_scenario<S: AsRef<Path>>(name: &str, search_path: &[S]) -> Result<PathBuf, SkyError> {
+    let name = Path::new(name);
+
+    if let Some(path) = with_extension(name) {
+        return Ok(path);
+    }
+
+    if name.is_absolute() {
+        return Err(SkyError::Config(format!(
+            "Scenario file {:?} does not exist",
+            name
+        )));
+    }
+
+    let mut dirs: Vec<PathBuf> = search_path.iter().map(|p| p.as_ref().to_owned()).collect();
+
+    if let Some(paths) = env::var_os(SCENARIO_PATH_VAR) {
+        dirs.extend(env::split_paths(&paths));
+    }
+
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(Path::new(&home).join(".scaii/backends/sky-rts/maps"));
+    }
+
+    for dir in &dirs {
+        if let Some(path) = with_extension(&dir.join(name)) {
+            return Ok(path);
+        }
+    }
+
+    Err(SkyError::Config(format!(
+        "Could not find scenario {:?}, searched the working directory and {:?}",
+        name, dirs
+    )))
+}
+
+fn with_extension(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_owned());
+    }
+
+    let mut path = path.as_os_str().to_owned();
+    path.push(".lua");
+
+    let path = PathBuf::from(path);
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_scenario;
+    use std::path::PathBuf;
+
+    #[test]
+    fn resolves_search_path() {
+        let lua_dir = PathBuf::from(format!("{}/lua", env!("CARGO_MANIFEST_DIR")));
+        let expected = lua_dir.join("tower_example.lua");
+
+        assert_eq!(
+            resolve_scenario("tower_example", &[&lua_dir]).unwrap(),
+            expected
+        );
+        assert_eq!(
+            resolve_scenario(expected.to_str().unwrap(), &[] as &[PathBuf]).unwrap(),
+            expected
+        );
+        assert!(resolve_scenario("not_a_scenario", &[&lua_dir]).is_err());
+    }
+}
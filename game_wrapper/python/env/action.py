@@ -26,7 +26,8 @@ class MoveList(Action):
             action.unit_id = move[0] - 1
 
             if move[1] == "move":
-                action.move_to.pos = move[2]
+                action.move_to.pos.x = move[2][0]
+                action.move_to.pos.y = move[2][1]
             elif move[1] == "attack":
                 action.attack_unit.target_id = move[2] - 1
             else:
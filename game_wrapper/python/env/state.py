@@ -2,33 +2,85 @@ from scaii.env.state import State
 
 # pylint: disable=locally-disabled, E1101
 
+# The backend's layers when neither the scenario nor the
+# `Config` picks any, as (name, categorical) pairs
+DEFAULT_LAYERS = [("unit_id", True), ("hp", False),
+                  ("unit_type", True), ("faction", True)]
+
+# Categorical layers holding entity IDs, which
+# aren't one-hot encoded
+ID_LAYERS = ["unit_id", "attack_target"]
+
 
 class SkyState(State):
+    # The feature layers are only sent with the first state of an
+    # episode, so they're remembered for the states after it
+    feature_layers = None
+
     def __init__(self, typed_reward=None, reward=None, terminal=False, state=None, env_state=None):
         import numpy as np
+        from ..protos.sky_rts_pb2 import FeatureLayer, StateUpdate
 
         super().__init__(typed_reward, reward, terminal, state, env_state)
 
-        id_map = state[:, :, 0].astype(np.int)
+        self.old_state = state
+
+        self.update = StateUpdate()
+        if env_state:
+            self.update.ParseFromString(env_state)
+
+        if self.update.feature_layers:
+            SkyState.feature_layers = list(self.update.feature_layers)
+
+        if SkyState.feature_layers is None:
+            SkyState.feature_layers = [FeatureLayer(name=name, categorical=categorical)
+                                       for name, categorical in DEFAULT_LAYERS]
+
+        # Episodes cut off by their time limit aren't terminal, so values
+        # can bootstrap from their last state, but still need a reset
+        self.truncated = self.update.HasField(
+            "outcome") and self.update.outcome.truncated
+        self.is_over = terminal or self.truncated
+
+        self.layers = SkyState.feature_layers
+        self.layer_index = {layer.name: i for i,
+                            layer in enumerate(self.layers)}
+
+        if "unit_id" in self.layer_index:
+            id_map = self.channel("unit_id").astype(np.int)
+        else:
+            id_map = np.zeros(state.shape[:2], dtype=np.int)
         id_list = np.unique(id_map).astype(np.int)[1:]
 
         self.id_list = id_list
 
         self.id_map = id_map
 
-        self.state = np.empty(
-            (state.shape[0], state.shape[1], 6), dtype=np.float)
+        # Magnitudes are scaled into [0, 1] by the layer's bound, while
+        # categorical layers are one-hot encoded (without the 0 for none)
+        channels = []
+        for i, layer in enumerate(self.layers):
+            values = state[:, :, i]
 
-        # Normalize HP
-        self.state[:, :, 0] = state[:, :, 1] / 500.0
+            if layer.name in ID_LAYERS:
+                continue
+            elif layer.categorical:
+                if not layer.HasField("max_value"):
+                    continue
+                categories = np.arange(1, int(layer.max_value) + 1)
+                channels.append(np.equal.outer(
+                    values.astype(np.int), categories).astype(np.float))
+            else:
+                scale = layer.max_value if layer.HasField(
+                    "max_value") else 1.0
+                channels.append((values / scale)[:, :, np.newaxis])
 
-        unit_ids = state[:, :, 2].astype(np.int)
+        if channels:
+            self.state = np.concatenate(channels, axis=2)
+        else:
+            self.state = np.empty(
+                (state.shape[0], state.shape[1], 0), dtype=np.float)
 
-        self.state[:, :, 1:4] = np.equal.outer(unit_ids, [0, 1, 2, 3]).astype(np.float)[
-            :, :, 1:]
-
-        faction_ids = state[:, :, 3].astype(np.int)
-        self.state[:, :, 4:] = np.equal.outer(
-            faction_ids, np.unique(faction_ids)).astype(np.float)[:, :, 1:]
-
-        self.old_state = state
+    def channel(self, name):
+        """The unscaled values of the feature layer called `name`."""
+        return self.old_state[:, :, self.layer_index[name]]
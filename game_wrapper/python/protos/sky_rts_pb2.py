@@ -19,7 +19,7 @@ DESCRIPTOR = _descriptor.FileDescriptor(
   name='sky-rts.proto',
   package='scaii.rts',
   syntax='proto2',
-  serialized_pb=_b('\n\rsky-rts.proto\x12\tscaii.rts\"T\n\nActionList\x12&\n\x07\x61\x63tions\x18\x01 \x03(\x0b\x32\x15.scaii.rts.UnitAction\x12\x0c\n\x04skip\x18\x02 \x01(\x08\x12\x10\n\x08skip_lua\x18\x03 \x01(\t\"{\n\nUnitAction\x12\x0f\n\x07unit_id\x18\x01 \x02(\x04\x12$\n\x07move_to\x18\x02 \x01(\x0b\x32\x11.scaii.rts.MoveToH\x00\x12,\n\x0b\x61ttack_unit\x18\x03 \x01(\x0b\x32\x15.scaii.rts.AttackUnitH\x00\x42\x08\n\x06\x61\x63tion\"\x1b\n\x03Pos\x12\t\n\x01x\x18\x01 \x02(\x01\x12\t\n\x01y\x18\x02 \x02(\x01\"%\n\x06MoveTo\x12\x1b\n\x03pos\x18\x01 \x02(\x0b\x32\x0e.scaii.rts.Pos\"\x1f\n\nAttackUnit\x12\x11\n\ttarget_id\x18\x01 \x02(\r\"V\n\x0bStateUpdate\x12\x1e\n\x05units\x18\x01 \x03(\x0b\x32\x0f.scaii.rts.Unit\x12\'\n\nunit_types\x18\x02 \x03(\x0b\x32\x13.scaii.rts.UnitType\"P\n\x08UnitType\x12\x14\n\x0cunit_type_id\x18\x01 \x02(\x04\x12\x0e\n\x06max_hp\x18\x02 \x01(\x01\x12\x0f\n\x07movable\x18\x03 \x01(\x01\x12\r\n\x03tag\x18\x04 \x01(\t:\x00\"x\n\x04Unit\x12\n\n\x02id\x18\x01 \x02(\r\x12\x10\n\x08owner_id\x18\x02 \x01(\x04\x12\x14\n\x0cunit_type_id\x18\x03 \x01(\x04\x12 \n\x03pos\x18\x04 \x01(\x0b\x32\x13.scaii.rts.DeltaPos\x12\n\n\x02hp\x18\x05 \x01(\x01\x12\x0e\n\x06\x64\x65lete\x18\n \x02(\x08\" \n\x08\x44\x65ltaPos\x12\t\n\x01x\x18\x01 \x01(\x01\x12\t\n\x01y\x18\x02 \x01(\x01\"g\n\x06\x43onfig\x12%\n\x08scenario\x18\x01 \x01(\x0b\x32\x13.scaii.rts.Scenario\x12\x10\n\x08\x65mit_viz\x18\x02 \x01(\x08\x12$\n\x0brandom_seed\x18\x03 \x01(\x0b\x32\x0f.scaii.rts.Seed\"\x14\n\x04Seed\x12\x0c\n\x04seed\x18\x01 \x03(\x04\"\x18\n\x08Scenario\x12\x0c\n\x04path\x18\x01 \x02(\t')
+  serialized_pb=_b('\n\rsky-rts.proto\x12\tscaii.rts\"\x96\x01\n\nActionList\x12&\n\x07\x61\x63tions\x18\x01 \x03(\x0b\x32\x15.scaii.rts.UnitAction\x12\x0c\n\x04skip\x18\x02 \x01(\x08\x12\x10\n\x08skip_lua\x18\x03 \x01(\t\x12\x32\n\x0f\x66\x61\x63tion_actions\x18\x04 \x03(\x0b\x32\x19.scaii.rts.FactionActions\x12\x0c\n\x04seek\x18\x05 \x01(\x04\"I\n\x0e\x46\x61\x63tionActions\x12\x0f\n\x07\x66\x61\x63tion\x18\x01 \x02(\r\x12&\n\x07\x61\x63tions\x18\x02 \x03(\x0b\x32\x15.scaii.rts.UnitAction\"{\n\nUnitAction\x12\x0f\n\x07unit_id\x18\x01 \x02(\x04\x12$\n\x07move_to\x18\x02 \x01(\x0b\x32\x11.scaii.rts.MoveToH\x00\x12,\n\x0b\x61ttack_unit\x18\x03 \x01(\x0b\x32\x15.scaii.rts.AttackUnitH\x00\x42\x08\n\x06\x61\x63tion\"\x1b\n\x03Pos\x12\t\n\x01x\x18\x01 \x02(\x01\x12\t\n\x01y\x18\x02 \x02(\x01\"%\n\x06MoveTo\x12\x1b\n\x03pos\x18\x01 \x02(\x0b\x32\x0e.scaii.rts.Pos\"\x1f\n\nAttackUnit\x12\x11\n\ttarget_id\x18\x01 \x02(\r\"\xac\x01\n\x0bStateUpdate\x12\x1e\n\x05units\x18\x01 \x03(\x0b\x32\x0f.scaii.rts.Unit\x12\'\n\nunit_types\x18\x02 \x03(\x0b\x32\x13.scaii.rts.UnitType\x12/\n\x0e\x66\x65\x61ture_layers\x18\x03 \x03(\x0b\x32\x17.scaii.rts.FeatureLayer\x12#\n\x07outcome\x18\x04 \x01(\x0b\x32\x12.scaii.rts.Outcome\",\n\x07Outcome\x12\x0e\n\x06winner\x18\x01 \x01(\r\x12\x11\n\ttruncated\x18\x02 \x01(\x08\"D\n\x0c\x46\x65\x61tureLayer\x12\x0c\n\x04name\x18\x01 \x02(\t\x12\x13\n\x0b\x63\x61tegorical\x18\x02 \x02(\x08\x12\x11\n\tmax_value\x18\x03 \x01(\x01\"P\n\x08UnitType\x12\x14\n\x0cunit_type_id\x18\x01 \x02(\x04\x12\x0e\n\x06max_hp\x18\x02 \x01(\x01\x12\x0f\n\x07movable\x18\x03 \x01(\x01\x12\r\n\x03tag\x18\x04 \x01(\t:\x00\"x\n\x04Unit\x12\n\n\x02id\x18\x01 \x02(\r\x12\x10\n\x08owner_id\x18\x02 \x01(\x04\x12\x14\n\x0cunit_type_id\x18\x03 \x01(\x04\x12 \n\x03pos\x18\x04 \x01(\x0b\x32\x13.scaii.rts.DeltaPos\x12\n\n\x02hp\x18\x05 \x01(\x01\x12\x0e\n\x06\x64\x65lete\x18\n \x02(\x08\" \n\x08\x44\x65ltaPos\x12\t\n\x01x\x18\x01 \x01(\x01\x12\t\n\x01y\x18\x02 \x01(\x01\"\xea\x02\n\x06\x43onfig\x12%\n\x08scenario\x18\x01 \x01(\x0b\x32\x13.scaii.rts.Scenario\x12\x10\n\x08\x65mit_viz\x18\x02 \x01(\x08\x12$\n\x0brandom_seed\x18\x03 \x01(\x0b\x32\x0f.scaii.rts.Seed\x12\x1c\n\x14scenario_search_path\x18\x04 \x03(\t\x12\x31\n\x0bobservation\x18\x05 \x01(\x0b\x32\x1c.scaii.rts.ObservationConfig\x12\x16\n\x0e\x66\x65\x61ture_layers\x18\x06 \x03(\t\x12\x16\n\x0e\x61gent_factions\x18\x07 \x03(\r\x12\x18\n\x10max_episode_time\x18\x08 \x01(\x01\x12\x11\n\tmax_steps\x18\t \x01(\x04\x12\x13\n\x0btick_length\x18\n \x01(\x01\x12\x15\n\raction_repeat\x18\x0b \x01(\r\x12\x12\n\nreplay_dir\x18\x0c \x01(\t\x12\x13\n\x0breplay_file\x18\r \x01(\t\"e\n\x11ObservationConfig\x12\r\n\x05width\x18\x01 \x01(\r\x12\x0e\n\x06height\x18\x02 \x01(\r\x12\x11\n\tcell_size\x18\x03 \x01(\x01\x12\x1e\n\x06origin\x18\x04 \x01(\x0b\x32\x0e.scaii.rts.Pos\"\x14\n\x04Seed\x12\x0c\n\x04seed\x18\x01 \x03(\x04\",\n\x08Scenario\x12\x0c\n\x04path\x18\x01 \x02(\t\x12\x12\n\nlua_source\x18\x02 \x01(\t')
 )
 
 
@@ -53,6 +53,20 @@ _ACTIONLIST = _descriptor.Descriptor(
       message_type=None, enum_type=None, containing_type=None,
       is_extension=False, extension_scope=None,
       options=None),
+    _descriptor.FieldDescriptor(
+      name='faction_actions', full_name='scaii.rts.ActionList.faction_actions', index=3,
+      number=4, type=11, cpp_type=10, label=3,
+      has_default_value=False, default_value=[],
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='seek', full_name='scaii.rts.ActionList.seek', index=4,
+      number=5, type=4, cpp_type=4, label=1,
+      has_default_value=False, default_value=0,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
   ],
   extensions=[
   ],
@@ -65,8 +79,46 @@ _ACTIONLIST = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=28,
-  serialized_end=112,
+  serialized_start=29,
+  serialized_end=179,
+)
+
+
+_FACTIONACTIONS = _descriptor.Descriptor(
+  name='FactionActions',
+  full_name='scaii.rts.FactionActions',
+  filename=None,
+  file=DESCRIPTOR,
+  containing_type=None,
+  fields=[
+    _descriptor.FieldDescriptor(
+      name='faction', full_name='scaii.rts.FactionActions.faction', index=0,
+      number=1, type=13, cpp_type=3, label=2,
+      has_default_value=False, default_value=0,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='actions', full_name='scaii.rts.FactionActions.actions', index=1,
+      number=2, type=11, cpp_type=10, label=3,
+      has_default_value=False, default_value=[],
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+  ],
+  extensions=[
+  ],
+  nested_types=[],
+  enum_types=[
+  ],
+  options=None,
+  is_extendable=False,
+  syntax='proto2',
+  extension_ranges=[],
+  oneofs=[
+  ],
+  serialized_start=181,
+  serialized_end=254,
 )
 
 
@@ -113,8 +165,8 @@ _UNITACTION = _descriptor.Descriptor(
       name='action', full_name='scaii.rts.UnitAction.action',
       index=0, containing_type=None, fields=[]),
   ],
-  serialized_start=114,
-  serialized_end=237,
+  serialized_start=256,
+  serialized_end=379,
 )
 
 
@@ -151,8 +203,8 @@ _POS = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=239,
-  serialized_end=266,
+  serialized_start=381,
+  serialized_end=408,
 )
 
 
@@ -182,8 +234,8 @@ _MOVETO = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=268,
-  serialized_end=305,
+  serialized_start=410,
+  serialized_end=447,
 )
 
 
@@ -213,8 +265,8 @@ _ATTACKUNIT = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=307,
-  serialized_end=338,
+  serialized_start=449,
+  serialized_end=480,
 )
 
 
@@ -239,6 +291,103 @@ _STATEUPDATE = _descriptor.Descriptor(
       message_type=None, enum_type=None, containing_type=None,
       is_extension=False, extension_scope=None,
       options=None),
+    _descriptor.FieldDescriptor(
+      name='feature_layers', full_name='scaii.rts.StateUpdate.feature_layers', index=2,
+      number=3, type=11, cpp_type=10, label=3,
+      has_default_value=False, default_value=[],
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='outcome', full_name='scaii.rts.StateUpdate.outcome', index=3,
+      number=4, type=11, cpp_type=10, label=1,
+      has_default_value=False, default_value=None,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+  ],
+  extensions=[
+  ],
+  nested_types=[],
+  enum_types=[
+  ],
+  options=None,
+  is_extendable=False,
+  syntax='proto2',
+  extension_ranges=[],
+  oneofs=[
+  ],
+  serialized_start=483,
+  serialized_end=655,
+)
+
+
+_OUTCOME = _descriptor.Descriptor(
+  name='Outcome',
+  full_name='scaii.rts.Outcome',
+  filename=None,
+  file=DESCRIPTOR,
+  containing_type=None,
+  fields=[
+    _descriptor.FieldDescriptor(
+      name='winner', full_name='scaii.rts.Outcome.winner', index=0,
+      number=1, type=13, cpp_type=3, label=1,
+      has_default_value=False, default_value=0,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='truncated', full_name='scaii.rts.Outcome.truncated', index=1,
+      number=2, type=8, cpp_type=7, label=1,
+      has_default_value=False, default_value=False,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+  ],
+  extensions=[
+  ],
+  nested_types=[],
+  enum_types=[
+  ],
+  options=None,
+  is_extendable=False,
+  syntax='proto2',
+  extension_ranges=[],
+  oneofs=[
+  ],
+  serialized_start=657,
+  serialized_end=701,
+)
+
+
+_FEATURELAYER = _descriptor.Descriptor(
+  name='FeatureLayer',
+  full_name='scaii.rts.FeatureLayer',
+  filename=None,
+  file=DESCRIPTOR,
+  containing_type=None,
+  fields=[
+    _descriptor.FieldDescriptor(
+      name='name', full_name='scaii.rts.FeatureLayer.name', index=0,
+      number=1, type=9, cpp_type=9, label=2,
+      has_default_value=False, default_value=_b("").decode('utf-8'),
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='categorical', full_name='scaii.rts.FeatureLayer.categorical', index=1,
+      number=2, type=8, cpp_type=7, label=2,
+      has_default_value=False, default_value=False,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='max_value', full_name='scaii.rts.FeatureLayer.max_value', index=2,
+      number=3, type=1, cpp_type=5, label=1,
+      has_default_value=False, default_value=float(0),
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
   ],
   extensions=[
   ],
@@ -251,8 +400,8 @@ _STATEUPDATE = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=340,
-  serialized_end=426,
+  serialized_start=703,
+  serialized_end=771,
 )
 
 
@@ -303,8 +452,8 @@ _UNITTYPE = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=428,
-  serialized_end=508,
+  serialized_start=773,
+  serialized_end=853,
 )
 
 
@@ -369,8 +518,8 @@ _UNIT = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=510,
-  serialized_end=630,
+  serialized_start=855,
+  serialized_end=975,
 )
 
 
@@ -407,8 +556,8 @@ _DELTAPOS = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=632,
-  serialized_end=664,
+  serialized_start=977,
+  serialized_end=1009,
 )
 
 
@@ -440,6 +589,76 @@ _CONFIG = _descriptor.Descriptor(
       message_type=None, enum_type=None, containing_type=None,
       is_extension=False, extension_scope=None,
       options=None),
+    _descriptor.FieldDescriptor(
+      name='scenario_search_path', full_name='scaii.rts.Config.scenario_search_path', index=3,
+      number=4, type=9, cpp_type=9, label=3,
+      has_default_value=False, default_value=[],
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='observation', full_name='scaii.rts.Config.observation', index=4,
+      number=5, type=11, cpp_type=10, label=1,
+      has_default_value=False, default_value=None,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='feature_layers', full_name='scaii.rts.Config.feature_layers', index=5,
+      number=6, type=9, cpp_type=9, label=3,
+      has_default_value=False, default_value=[],
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='agent_factions', full_name='scaii.rts.Config.agent_factions', index=6,
+      number=7, type=13, cpp_type=3, label=3,
+      has_default_value=False, default_value=[],
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='max_episode_time', full_name='scaii.rts.Config.max_episode_time', index=7,
+      number=8, type=1, cpp_type=5, label=1,
+      has_default_value=False, default_value=float(0),
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='max_steps', full_name='scaii.rts.Config.max_steps', index=8,
+      number=9, type=4, cpp_type=4, label=1,
+      has_default_value=False, default_value=0,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='tick_length', full_name='scaii.rts.Config.tick_length', index=9,
+      number=10, type=1, cpp_type=5, label=1,
+      has_default_value=False, default_value=float(0),
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='action_repeat', full_name='scaii.rts.Config.action_repeat', index=10,
+      number=11, type=13, cpp_type=3, label=1,
+      has_default_value=False, default_value=0,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='replay_dir', full_name='scaii.rts.Config.replay_dir', index=11,
+      number=12, type=9, cpp_type=9, label=1,
+      has_default_value=False, default_value=_b("").decode('utf-8'),
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='replay_file', full_name='scaii.rts.Config.replay_file', index=12,
+      number=13, type=9, cpp_type=9, label=1,
+      has_default_value=False, default_value=_b("").decode('utf-8'),
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
   ],
   extensions=[
   ],
@@ -452,8 +671,60 @@ _CONFIG = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=666,
-  serialized_end=769,
+  serialized_start=1012,
+  serialized_end=1374,
+)
+
+
+_OBSERVATIONCONFIG = _descriptor.Descriptor(
+  name='ObservationConfig',
+  full_name='scaii.rts.ObservationConfig',
+  filename=None,
+  file=DESCRIPTOR,
+  containing_type=None,
+  fields=[
+    _descriptor.FieldDescriptor(
+      name='width', full_name='scaii.rts.ObservationConfig.width', index=0,
+      number=1, type=13, cpp_type=3, label=1,
+      has_default_value=False, default_value=0,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='height', full_name='scaii.rts.ObservationConfig.height', index=1,
+      number=2, type=13, cpp_type=3, label=1,
+      has_default_value=False, default_value=0,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='cell_size', full_name='scaii.rts.ObservationConfig.cell_size', index=2,
+      number=3, type=1, cpp_type=5, label=1,
+      has_default_value=False, default_value=float(0),
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+    _descriptor.FieldDescriptor(
+      name='origin', full_name='scaii.rts.ObservationConfig.origin', index=3,
+      number=4, type=11, cpp_type=10, label=1,
+      has_default_value=False, default_value=None,
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
+  ],
+  extensions=[
+  ],
+  nested_types=[],
+  enum_types=[
+  ],
+  options=None,
+  is_extendable=False,
+  syntax='proto2',
+  extension_ranges=[],
+  oneofs=[
+  ],
+  serialized_start=1376,
+  serialized_end=1477,
 )
 
 
@@ -483,8 +754,8 @@ _SEED = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=771,
-  serialized_end=791,
+  serialized_start=1479,
+  serialized_end=1499,
 )
 
 
@@ -502,6 +773,13 @@ _SCENARIO = _descriptor.Descriptor(
       message_type=None, enum_type=None, containing_type=None,
       is_extension=False, extension_scope=None,
       options=None),
+    _descriptor.FieldDescriptor(
+      name='lua_source', full_name='scaii.rts.Scenario.lua_source', index=1,
+      number=2, type=9, cpp_type=9, label=1,
+      has_default_value=False, default_value=_b("").decode('utf-8'),
+      message_type=None, enum_type=None, containing_type=None,
+      is_extension=False, extension_scope=None,
+      options=None),
   ],
   extensions=[
   ],
@@ -514,11 +792,13 @@ _SCENARIO = _descriptor.Descriptor(
   extension_ranges=[],
   oneofs=[
   ],
-  serialized_start=793,
-  serialized_end=817,
+  serialized_start=1501,
+  serialized_end=1545,
 )
 
 _ACTIONLIST.fields_by_name['actions'].message_type = _UNITACTION
+_ACTIONLIST.fields_by_name['faction_actions'].message_type = _FACTIONACTIONS
+_FACTIONACTIONS.fields_by_name['actions'].message_type = _UNITACTION
 _UNITACTION.fields_by_name['move_to'].message_type = _MOVETO
 _UNITACTION.fields_by_name['attack_unit'].message_type = _ATTACKUNIT
 _UNITACTION.oneofs_by_name['action'].fields.append(
@@ -530,19 +810,27 @@ _UNITACTION.fields_by_name['attack_unit'].containing_oneof = _UNITACTION.oneofs_
 _MOVETO.fields_by_name['pos'].message_type = _POS
 _STATEUPDATE.fields_by_name['units'].message_type = _UNIT
 _STATEUPDATE.fields_by_name['unit_types'].message_type = _UNITTYPE
+_STATEUPDATE.fields_by_name['feature_layers'].message_type = _FEATURELAYER
+_STATEUPDATE.fields_by_name['outcome'].message_type = _OUTCOME
 _UNIT.fields_by_name['pos'].message_type = _DELTAPOS
 _CONFIG.fields_by_name['scenario'].message_type = _SCENARIO
 _CONFIG.fields_by_name['random_seed'].message_type = _SEED
+_CONFIG.fields_by_name['observation'].message_type = _OBSERVATIONCONFIG
+_OBSERVATIONCONFIG.fields_by_name['origin'].message_type = _POS
 DESCRIPTOR.message_types_by_name['ActionList'] = _ACTIONLIST
+DESCRIPTOR.message_types_by_name['FactionActions'] = _FACTIONACTIONS
 DESCRIPTOR.message_types_by_name['UnitAction'] = _UNITACTION
 DESCRIPTOR.message_types_by_name['Pos'] = _POS
 DESCRIPTOR.message_types_by_name['MoveTo'] = _MOVETO
 DESCRIPTOR.message_types_by_name['AttackUnit'] = _ATTACKUNIT
 DESCRIPTOR.message_types_by_name['StateUpdate'] = _STATEUPDATE
+DESCRIPTOR.message_types_by_name['Outcome'] = _OUTCOME
+DESCRIPTOR.message_types_by_name['FeatureLayer'] = _FEATURELAYER
 DESCRIPTOR.message_types_by_name['UnitType'] = _UNITTYPE
 DESCRIPTOR.message_types_by_name['Unit'] = _UNIT
 DESCRIPTOR.message_types_by_name['DeltaPos'] = _DELTAPOS
 DESCRIPTOR.message_types_by_name['Config'] = _CONFIG
+DESCRIPTOR.message_types_by_name['ObservationConfig'] = _OBSERVATIONCONFIG
 DESCRIPTOR.message_types_by_name['Seed'] = _SEED
 DESCRIPTOR.message_types_by_name['Scenario'] = _SCENARIO
 _sym_db.RegisterFileDescriptor(DESCRIPTOR)
@@ -554,6 +842,13 @@ ActionList = _reflection.GeneratedProtocolMessageType('ActionList', (_message.Me
   ))
 _sym_db.RegisterMessage(ActionList)
 
+FactionActions = _reflection.GeneratedProtocolMessageType('FactionActions', (_message.Message,), dict(
+  DESCRIPTOR = _FACTIONACTIONS,
+  __module__ = 'sky_rts_pb2'
+  # @@protoc_insertion_point(class_scope:scaii.rts.FactionActions)
+  ))
+_sym_db.RegisterMessage(FactionActions)
+
 UnitAction = _reflection.GeneratedProtocolMessageType('UnitAction', (_message.Message,), dict(
   DESCRIPTOR = _UNITACTION,
   __module__ = 'sky_rts_pb2'
@@ -589,6 +884,20 @@ StateUpdate = _reflection.GeneratedProtocolMessageType('StateUpdate', (_message.
   ))
 _sym_db.RegisterMessage(StateUpdate)
 
+Outcome = _reflection.GeneratedProtocolMessageType('Outcome', (_message.Message,), dict(
+  DESCRIPTOR = _OUTCOME,
+  __module__ = 'sky_rts_pb2'
+  # @@protoc_insertion_point(class_scope:scaii.rts.Outcome)
+  ))
+_sym_db.RegisterMessage(Outcome)
+
+FeatureLayer = _reflection.GeneratedProtocolMessageType('FeatureLayer', (_message.Message,), dict(
+  DESCRIPTOR = _FEATURELAYER,
+  __module__ = 'sky_rts_pb2'
+  # @@protoc_insertion_point(class_scope:scaii.rts.FeatureLayer)
+  ))
+_sym_db.RegisterMessage(FeatureLayer)
+
 UnitType = _reflection.GeneratedProtocolMessageType('UnitType', (_message.Message,), dict(
   DESCRIPTOR = _UNITTYPE,
   __module__ = 'sky_rts_pb2'
@@ -617,6 +926,13 @@ Config = _reflection.GeneratedProtocolMessageType('Config', (_message.Message,),
   ))
 _sym_db.RegisterMessage(Config)
 
+ObservationConfig = _reflection.GeneratedProtocolMessageType('ObservationConfig', (_message.Message,), dict(
+  DESCRIPTOR = _OBSERVATIONCONFIG,
+  __module__ = 'sky_rts_pb2'
+  # @@protoc_insertion_point(class_scope:scaii.rts.ObservationConfig)
+  ))
+_sym_db.RegisterMessage(ObservationConfig)
+
 Seed = _reflection.GeneratedProtocolMessageType('Seed', (_message.Message,), dict(
   DESCRIPTOR = _SEED,
   __module__ = 'sky_rts_pb2'